@@ -10,6 +10,10 @@ extern crate pta_parser;
 use log::{info, warn, error};
 
 // TODO: how to isolate pest so clients can just use lib (w/o requiring pest as here)
+use pta_ledger::booking::book;
+use pta_ledger::padding::resolve_pads;
+use pta_ledger::plugins::PluginRegistry;
+use pta_ledger::validation::{validate, ValidationOptions};
 use pta_ledger::ledger_builder::LedgerBuilder;
 
 
@@ -28,28 +32,36 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("Input file: {:?}", p);
 
     let mut bldr = LedgerBuilder::default();
-    match std::fs::read_to_string(p) {
-        Ok(ledger) => {
-            info!("String length from input: {}", ledger.len());
-            match bldr.from_string(&ledger) {
-                Ok(_parsed) => {
-                    info!("Successfully parsed into ParsedLedger");
-                    return Ok(());
-                },
-
-                Err(e) => {
-                    error!("LedgerBuilder failed with {:}", e);
-                    return Err(e);
-                }
+    match bldr.from_path(&p) {
+        Ok(parsed) => {
+            info!("Successfully parsed into ParsedLedger");
+
+            let parsed = &mut std::mem::take(parsed);
+            let options = ValidationOptions::from_options(parsed.options(), bldr.dialect());
+            for err in resolve_pads(parsed, &options.tolerance) {
+                warn!("{err}");
             }
-        }
+
+            let booking = book(parsed, parsed.options().booking_method);
+            for err in booking.errors {
+                warn!("{err}");
+            }
+
+            for err in PluginRegistry::with_builtins().run_plugins(parsed) {
+                warn!("{err}");
+            }
+
+            for err in validate(parsed, &options) {
+                warn!("{err}");
+            }
+            return Ok(());
+        },
 
         Err(e) => {
-            warn!("failed to read file as string; {e}");
-            return Err(Box::new(e));
+            error!("LedgerBuilder failed with {:}", e);
+            return Err(e);
         }
     }
-
 }
 
 
@@ -0,0 +1,188 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use pta_types::*;
+
+use crate::balance_assertions::{is_same_or_sub_account, RunningBalances};
+use crate::ledger_error::*;
+
+
+// The open and (optional) close directives of an account
+struct Lifecycle<'a> {
+    open: &'a RawOpen,
+    close: Option<&'a RawClose>,
+}
+
+impl Lifecycle<'_> {
+    fn is_open_on(self: &Self, date: &str) -> bool {
+        return self.open.date.as_str() <= date
+            && self.close.is_none_or(|c| date <= c.date.as_str());
+    }
+}
+
+
+// Checks that postings only use accounts while they are open, that each account is opened once,
+//  and that accounts are closed with a zero balance and after their sub-accounts. Unless
+//  'require_open', as in the journals of ledger-cli and hledger, accounts need not be opened.
+pub fn check_account_lifecycles(pl: &ParsedLedger, require_open: bool) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+    let mut lifecycles: BTreeMap<&str, Lifecycle> = BTreeMap::new();
+
+    for d in pl.directives() {
+        if let RawDirective::Open(open) = d {
+            match lifecycles.get(open.acct.path.as_str()) {
+                Some(first) => {
+                    errs.push(LedgerError::new(LedgerErrorKind::DuplicateOpen,
+                        format!("account {} is already opened at line {}", open.acct.path, first.open.pinfo.position.line),
+                        &open.pinfo));
+                }
+                None => { lifecycles.insert(&open.acct.path, Lifecycle { open, close: None }); }
+            }
+        }
+    }
+
+    let mut closes: Vec<&RawClose> = Vec::new();
+    for d in pl.directives() {
+        if let RawDirective::Close(close) = d {
+            match lifecycles.get_mut(close.acct.path.as_str()) {
+                Some(lc) if lc.close.is_none() => {
+                    lc.close = Some(close);
+                    closes.push(close);
+                }
+                Some(_) => {
+                    errs.push(LedgerError::new(LedgerErrorKind::InactiveAccount,
+                        format!("account {} is already closed", close.acct.path), &close.pinfo));
+                }
+                None if require_open => {
+                    errs.push(LedgerError::new(LedgerErrorKind::AccountNotOpen,
+                        format!("cannot close account {}; it was never opened", close.acct.path), &close.pinfo));
+                }
+                None => {}
+            }
+        }
+    }
+
+    for xn in pl.transactions() {
+        for p in &xn.postings {
+            match lifecycles.get(p.acct.path.as_str()) {
+                None if require_open => {
+                    errs.push(LedgerError::new(LedgerErrorKind::AccountNotOpen,
+                        format!("posting to account {} which was never opened", p.acct.path), &p.pinfo));
+                }
+                None => {}
+                Some(lc) if xn.date < lc.open.date => {
+                    errs.push(LedgerError::new(LedgerErrorKind::InactiveAccount,
+                        format!("posting to account {} on {} before it was opened on {}", p.acct.path, xn.date, lc.open.date),
+                        &p.pinfo));
+                }
+                Some(Lifecycle { close: Some(close), .. }) if xn.date > close.date => {
+                    errs.push(LedgerError::new(LedgerErrorKind::InactiveAccount,
+                        format!("posting to account {} on {} after it was closed on {}", p.acct.path, xn.date, close.date),
+                        &p.pinfo));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    errs.extend(check_closes(pl, &lifecycles, closes));
+
+    return errs;
+}
+
+fn check_closes(pl: &ParsedLedger, lifecycles: &BTreeMap<&str, Lifecycle>, mut closes: Vec<&RawClose>) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let mut xns: Vec<&RawTransaction> = pl.transactions().collect();
+    xns.sort_by(|a, b| a.date.cmp(&b.date));
+    closes.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut balances = RunningBalances::default();
+    let mut pending = xns.into_iter().peekable();
+
+    for close in closes {
+        // postings on the date of the close are permitted, so include them in the balance
+        while let Some(xn) = pending.next_if(|xn| xn.date <= close.date) {
+            if let Err(e) = balances.apply(xn) {
+                errs.push(e);
+            }
+        }
+
+        let remaining: Vec<String> = balances.balances_of(&close.acct.path).map(|a| a.to_string()).collect();
+        if !remaining.is_empty() {
+            errs.push(LedgerError::new(LedgerErrorKind::CloseNonZeroBalance,
+                format!("closing account {} with non-zero balance {}", close.acct.path, remaining.join(", ")),
+                &close.pinfo));
+        }
+
+        for (acct, lc) in lifecycles.iter() {
+            if *acct != close.acct.path && is_same_or_sub_account(acct, &close.acct.path) && lc.is_open_on(&close.date)
+                && lc.close.is_none_or(|c| c.date > close.date) {
+                errs.push(LedgerError::new(LedgerErrorKind::CloseWithOpenSubAccounts,
+                    format!("closing account {} while its sub-account {} remains open", close.acct.path, acct),
+                    &close.pinfo));
+            }
+        }
+    }
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod account_lifecycle_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn check(ledger: &str) -> Vec<LedgerError> {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+        return check_account_lifecycles(pl, true);
+    }
+
+    const OPENS: &str = "2001-09-11 open Assets:Cash
+2001-09-11 open Equity
+";
+
+    #[rstest]
+    #[case ("2001-09-11 * \"on open date\"\n  Assets:Cash    1.00 USD\n  Equity    -1.00 USD\n")]
+    #[case ("2001-09-12 * \"xfer\"\n  Assets:Cash    1.00 USD\n  Equity\n2001-09-13 * \"xfer\"\n  Assets:Cash    -1.00 USD\n  Equity\n2001-09-13 close Assets:Cash\n")]
+    #[case ("2001-09-11 open Assets\n2001-09-12 close Assets:Cash\n2001-09-12 close Assets\n")]
+    fn can_validate_lifecycle(#[case] ledger: &str) {
+        let errs = check(&format!("{OPENS}{ledger}"));
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Bank    1.00 USD\n  Equity\n", LedgerErrorKind::AccountNotOpen, 4, "never opened")]
+    #[case ("2001-09-12 open Assets:Bank\n2001-09-11 * \"x\"\n  Assets:Bank    1.00 USD\n  Equity\n", LedgerErrorKind::InactiveAccount, 5, "before it was opened on 2001-09-12")]
+    #[case ("2001-09-12 close Equity\n2001-09-13 * \"x\"\n  Assets:Cash    0.00 USD\n  Equity\n", LedgerErrorKind::InactiveAccount, 6, "after it was closed on 2001-09-12")]
+    #[case ("2001-09-12 open Assets:Cash\n", LedgerErrorKind::DuplicateOpen, 3, "already opened at line 1")]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Cash    1.00 USD\n  Equity\n2001-09-13 close Assets:Cash\n", LedgerErrorKind::CloseNonZeroBalance, 6, "non-zero balance 1.00 USD")]
+    #[case ("2001-09-11 open Assets\n2001-09-12 close Assets\n", LedgerErrorKind::CloseWithOpenSubAccounts, 4, "sub-account Assets:Cash remains open")]
+    #[case ("2001-09-12 close Assets:Bank\n", LedgerErrorKind::AccountNotOpen, 3, "never opened")]
+    fn verify_lifecycle_error(#[case] ledger: &str, #[case] kind: LedgerErrorKind, #[case] line: usize, #[case] msg: &str) {
+        let errs = check(&format!("{OPENS}{ledger}"));
+        assert_eq!(errs.len(), 1, "expected a single error: {:?}", errs);
+        assert_eq!(errs[0].kind, kind);
+        assert_eq!(errs[0].pinfo.position.line, line);
+        assert!(errs[0].message.contains(msg), "missing '{}' in '{}'", msg, errs[0].message);
+    }
+
+    #[test]
+    fn verify_basic_ledger_lifecycle() {
+        let ledger = std::fs::read_to_string("../testdata/basic-ledger")
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = check(&ledger);
+        assert!(errs.iter().any(|e| e.kind == LedgerErrorKind::CloseWithOpenSubAccounts && e.pinfo.position.line == 4));
+        assert!(errs.iter().any(|e| e.kind == LedgerErrorKind::AccountNotOpen && e.pinfo.position.line == 22));
+    }
+}
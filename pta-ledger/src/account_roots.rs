@@ -0,0 +1,73 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeSet;
+
+use pta_types::*;
+
+use crate::ledger_error::*;
+
+
+// Checks that every account, as first named by a directive or posting, is under one of the root
+//  accounts (cf. the name_* options)
+pub fn check_account_roots(pl: &ParsedLedger, roots: &[String]) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+    let mut seen: BTreeSet<&str> = BTreeSet::new();
+
+    let directive_accts = pl.directives().flat_map(|d| match d {
+        RawDirective::Open(o) => vec![&o.acct],
+        RawDirective::Close(c) => vec![&c.acct],
+        RawDirective::Balance(b) => vec![&b.acct],
+        RawDirective::Note(n) => vec![&n.acct],
+        RawDirective::Document(doc) => vec![&doc.acct],
+        RawDirective::Pad(p) => vec![&p.acct, &p.source_acct],
+        _ => vec![],
+    });
+    let posting_accts = pl.transactions().flat_map(|xn| xn.postings.iter().map(|p| &p.acct));
+
+    for acct in directive_accts.chain(posting_accts) {
+        if !seen.insert(&acct.path) {
+            continue;
+        }
+
+        let root = acct.path.split(':').next().unwrap_or_default();
+        if !roots.iter().any(|r| r == root) {
+            errs.push(LedgerError::new(LedgerErrorKind::InvalidAccountRoot,
+                format!("account {} is not under any of the root accounts {}", acct.path, roots.join(", ")),
+                &acct.pinfo));
+        }
+    }
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod account_roots_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    #[rstest]
+    #[case ("", "Assets:Cash", 0)]
+    #[case ("", "Expenses", 0)]
+    #[case ("", "Actifs:Cash", 1)]
+    #[case ("", "Assetsx:Cash", 1)]
+    #[case ("option \"name_assets\" \"Actifs\"\n", "Actifs:Cash", 0)]
+    #[case ("option \"name_assets\" \"Actifs\"\n", "Assets:Cash", 1)]
+    fn can_check_account_roots(#[case] options: &str, #[case] acct: &str, #[case] errors: usize) {
+        let ledger = format!("{options}2001-09-11 open {acct}\n2001-09-12 * \"x\"\n  {acct}    1.00 USD\n  Equity\n");
+
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(&ledger).unwrap_or_else(|e| panic!("{}", e));
+        let roots: Vec<String> = pl.options().root_accounts().iter().map(|r| r.to_string()).collect();
+
+        let errs = check_account_roots(pl, &roots);
+        assert_eq!(errs.len(), errors, "unexpected errors: {:?}", errs);
+        assert!(errs.iter().all(|e| e.kind == LedgerErrorKind::InvalidAccountRoot && e.pinfo.position.col == 17));
+    }
+}
@@ -0,0 +1,320 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use pta_types::*;
+
+use crate::balancing::Tolerance;
+use crate::ledger_error::*;
+
+
+// Whether 'acct' is 'parent' or one of its sub-accounts
+pub fn is_same_or_sub_account(acct: &str, parent: &str) -> bool {
+    return acct == parent
+        || (acct.starts_with(parent) && acct[parent.len()..].starts_with(':'));
+}
+
+
+// The balance of each account, by commodity, as transactions are applied
+#[derive(Debug, Default, Clone)]
+pub struct RunningBalances {
+    balances: BTreeMap<String, BTreeMap<String, Decimal>>,
+}
+
+impl RunningBalances {
+    pub fn apply(self: &mut Self, xn: &RawTransaction) -> Result<(), LedgerError> {
+        for p in &xn.postings {
+            if let Some(amount) = &p.amount {
+                self.checked_add(&p.acct.path, amount).ok_or_else(|| LedgerError::new(LedgerErrorKind::Overflow,
+                    format!("balance of {} overflows", p.acct.path), &p.pinfo))?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Adds the amount to the account's balance; None on overflow
+    pub fn checked_add(self: &mut Self, acct: &str, amount: &Amount) -> Option<()> {
+        let bal = self.balances.entry(acct.to_string()).or_default()
+            .entry(amount.commodity.clone()).or_default();
+        *bal = bal.checked_add(&amount.number)?;
+
+        return Some(());
+    }
+
+    // The balance of the account alone
+    pub fn balance_of(self: &Self, acct: &str, commodity: &str) -> Decimal {
+        return self.balances.get(acct)
+            .and_then(|b| b.get(commodity))
+            .copied()
+            .unwrap_or_default();
+    }
+
+    // The balance of the account including its sub-accounts
+    pub fn total_of(self: &Self, acct: &str, commodity: &str) -> Option<Decimal> {
+        let mut total = Decimal::zero();
+
+        for (_, bals) in self.balances.iter().filter(|(a, _)| is_same_or_sub_account(a, acct)) {
+            if let Some(bal) = bals.get(commodity) {
+                total = total.checked_add(bal)?;
+            }
+        }
+
+        return Some(total);
+    }
+
+    // The balances of the account including its sub-accounts, by commodity; None on overflow
+    pub fn totals_by_commodity(self: &Self, acct: &str) -> Option<BTreeMap<String, Decimal>> {
+        let mut totals: BTreeMap<String, Decimal> = BTreeMap::new();
+
+        for (_, bals) in self.balances.iter().filter(|(a, _)| is_same_or_sub_account(a, acct)) {
+            for (commodity, bal) in bals {
+                let total = totals.entry(commodity.clone()).or_default();
+                *total = total.checked_add(bal)?;
+            }
+        }
+
+        return Some(totals);
+    }
+
+    // The non-zero balances of the account alone
+    pub fn balances_of(self: &Self, acct: &str) -> impl Iterator<Item = Amount> + '_ {
+        return self.balances.get(acct).into_iter()
+            .flat_map(|b| b.iter())
+            .filter(|(_, n)| !n.is_zero())
+            .map(|(c, n)| Amount::new(*n, c));
+    }
+}
+
+
+// The tolerance of a balance assertion, inferred from the precision of its amount
+pub fn assertion_tolerance(expected: &Amount, tolerance: &Tolerance) -> Decimal {
+    let scale = expected.number.scale();
+    if scale == 0 {
+        return tolerance.default_for(&expected.commodity);
+    }
+
+    return Decimal::new(1, scale).checked_mul(&tolerance.multiplier).unwrap_or_default();
+}
+
+
+// Checks each balance directive against the running balance of its account, including
+//  sub-accounts, as of the start of the directive's date (i.e., excluding that day's transactions)
+pub fn check_balance_assertions(pl: &ParsedLedger, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let mut xns: Vec<&RawTransaction> = pl.transactions().collect();
+    xns.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut assertions: Vec<&RawBalance> = pl.directives()
+        .filter_map(|d| match d { RawDirective::Balance(b) => Some(b), _ => None })
+        .collect();
+    assertions.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut balances = RunningBalances::default();
+    let mut pending = xns.into_iter().peekable();
+
+    for bal in assertions {
+        while let Some(xn) = pending.next_if(|xn| xn.date < bal.date) {
+            if let Err(e) = balances.apply(xn) {
+                errs.push(e);
+            }
+        }
+
+        let expected = &bal.amount;
+        let actual = match balances.total_of(&bal.acct.path, &expected.commodity) {
+            Some(actual) => actual,
+            None => {
+                errs.push(LedgerError::new(LedgerErrorKind::Overflow,
+                    format!("balance of {} overflows", bal.acct.path), &bal.pinfo));
+                continue;
+            }
+        };
+
+        let Some(diff) = actual.checked_sub(&expected.number) else {
+            errs.push(LedgerError::new(LedgerErrorKind::Overflow,
+                format!("balance difference of {} overflows", bal.acct.path), &bal.pinfo));
+            continue;
+        };
+
+        if diff.checked_abs().is_none_or(|abs| abs > assertion_tolerance(expected, tolerance)) {
+            errs.push(LedgerError::new(LedgerErrorKind::BalanceAssertion,
+                format!("balance assertion failed for {}: expected {}, actual {}, difference {}",
+                    bal.acct.path, expected, Amount::new(actual, &expected.commodity), Amount::new(diff, &expected.commodity)),
+                &bal.pinfo));
+        }
+    }
+
+    return errs;
+}
+
+
+// Checks each balance assertion on a posting against the balance of its account just after the
+//  posting, applying transactions in date order and, within a date, in ledger order
+pub fn check_posting_assertions(pl: &ParsedLedger, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let mut xns: Vec<&RawTransaction> = pl.transactions().collect();
+    xns.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut balances = RunningBalances::default();
+    for p in xns.iter().flat_map(|xn| xn.postings.iter()) {
+        if let Some(amount) = &p.amount {
+            if balances.checked_add(&p.acct.path, amount).is_none() {
+                errs.push(LedgerError::new(LedgerErrorKind::Overflow,
+                    format!("balance of {} overflows", p.acct.path), &p.pinfo));
+                continue;
+            }
+        }
+
+        if let Some(assertion) = &p.assertion {
+            errs.extend(check_posting_assertion(&balances, &p.acct.path, assertion, tolerance));
+        }
+    }
+
+    return errs;
+}
+
+fn check_posting_assertion(balances: &RunningBalances, acct: &str, assertion: &PostingAssertion, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+    let expected = &assertion.amount;
+
+    let actuals: BTreeMap<String, Decimal> = if assertion.inclusive {
+        match balances.totals_by_commodity(acct) {
+            Some(totals) => totals,
+            None => {
+                errs.push(LedgerError::new(LedgerErrorKind::Overflow, format!("balance of {acct} overflows"), &assertion.pinfo));
+                return errs;
+            }
+        }
+    } else {
+        balances.balances_of(acct).map(|a| (a.commodity, a.number)).collect()
+    };
+
+    let actual = actuals.get(&expected.commodity).copied().unwrap_or_default();
+    let Some(diff) = actual.checked_sub(&expected.number) else {
+        errs.push(LedgerError::new(LedgerErrorKind::Overflow, format!("balance difference of {acct} overflows"), &assertion.pinfo));
+        return errs;
+    };
+
+    if diff.checked_abs().is_none_or(|abs| abs > assertion_tolerance(expected, tolerance)) {
+        errs.push(LedgerError::new(LedgerErrorKind::BalanceAssertion,
+            format!("balance assertion failed for {}: expected {}, actual {}, difference {}",
+                acct, expected, Amount::new(actual, &expected.commodity), Amount::new(diff, &expected.commodity)),
+            &assertion.pinfo));
+    }
+
+    // == asserts that the account holds nothing else
+    if assertion.sole {
+        for (commodity, n) in actuals.iter().filter(|(c, n)| **c != expected.commodity && !n.is_zero()) {
+            errs.push(LedgerError::new(LedgerErrorKind::BalanceAssertion,
+                format!("balance assertion failed for {}: expected only {}, but it also holds {}", acct, expected.commodity, Amount::new(*n, commodity)),
+                &assertion.pinfo));
+        }
+    }
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod balance_assertions_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use pta_parser::parsers::Dialect;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn check(ledger: &str) -> Vec<LedgerError> {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+        return check_balance_assertions(pl, &Tolerance::default());
+    }
+
+    const LEDGER: &str = "2001-09-11 open Assets:Cash
+2001-09-12 * \"deposit\"
+  Assets:Cash:Petty    100.00 USD
+  Assets:Cash    23.456 USD
+  Equity    -123.456 USD
+
+2001-09-13 * \"spend\"
+  Assets:Cash    -3.456 USD
+  Expenses:Food
+";
+
+    #[rstest]
+    #[case ("2001-09-12 balance Assets:Cash 0.00 USD\n")]        // transactions on the date are excluded
+    #[case ("2001-09-13 balance Assets:Cash 123.456 USD\n")]     // sub-accounts are included
+    #[case ("2001-09-13 balance Assets:Cash:Petty 100.00 USD\n")]
+    #[case ("2001-09-14 balance Assets:Cash 120.000 USD\n")]
+    #[case ("2001-09-14 balance Expenses 3.46 USD\n")]           // within tolerance
+    #[case ("2001-09-14 balance Assets:Cash 0.00 BTC\n")]
+    #[case ("2001-09-14 balance Expenses 3.456 USD\n")]
+    fn can_check_balance_assertion(#[case] directive: &str) {
+        let errs = check(&format!("{LEDGER}{directive}"));
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("2001-09-13 balance Assets:Cash 23.456 USD\n", "expected 23.456 USD, actual 123.456 USD, difference 100.000 USD")]
+    #[case ("2001-09-14 balance Assets:Cash 120.01 USD\n", "expected 120.01 USD, actual 120.000 USD, difference -0.010 USD")]
+    #[case ("2001-09-14 balance Assets:Cas 120.000 USD\n", "expected 120.000 USD, actual 0 USD")]
+    fn verify_balance_assertion_error(#[case] directive: &str, #[case] msg: &str) {
+        let errs = check(&format!("{LEDGER}{directive}"));
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].kind, LedgerErrorKind::BalanceAssertion);
+        assert!(errs[0].message.contains(msg), "missing '{}' in '{}'", msg, errs[0].message);
+        assert_eq!(errs[0].pinfo.position.line, 10);
+    }
+
+    const JOURNAL: &str = "2023/01/05 Deposit
+    Assets:Cash  $100.00 = $100.00
+    Assets:Cash:Petty  $10.00
+    Assets:Cash  10 EUR
+    Equity
+";
+
+    fn check_postings(journal: &str) -> Vec<LedgerError> {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Hledger);
+        let pl = bldr.from_string(journal).unwrap_or_else(|e| panic!("{}", e));
+        return check_posting_assertions(pl, &Tolerance::default());
+    }
+
+    #[rstest]
+    #[case ("    Assets:Cash  $-1.00 = $99.00\n")]
+    #[case ("    Assets:Cash  $-1.00 =* $109.00\n")]                   // sub-accounts are included
+    #[case ("    Assets:Cash  -10 EUR == $100.00\n")]                  // nothing else is held
+    #[case ("    Assets:Cash:Petty  $-10.00\n    Assets:Cash  -10 EUR ==* $100.00\n")]
+    #[case ("    Assets:Cash  $-1.00 = 10 EUR\n")]
+    fn can_check_posting_assertion(#[case] postings: &str) {
+        let errs = check_postings(&format!("{JOURNAL}2023/01/06 Spend\n{postings}    Expenses\n"));
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("    Assets:Cash  $-1.00 = $100.00\n", "expected 100.00 $, actual 99.00 $, difference -1.00 $")]
+    #[case ("    Assets:Cash  $-1.00 = $109.00\n", "expected 109.00 $, actual 99.00 $")]
+    #[case ("    Assets:Cash  $-1.00 =* $99.00\n", "expected 99.00 $, actual 109.00 $")]
+    #[case ("    Assets:Cash  $-1.00 == $99.00\n", "expected only $, but it also holds 10 EUR")]
+    fn verify_posting_assertion_error(#[case] postings: &str, #[case] msg: &str) {
+        let errs = check_postings(&format!("{JOURNAL}2023/01/06 Spend\n{postings}    Expenses\n"));
+        assert_eq!(errs.len(), 1, "expected a single error: {:?}", errs);
+        assert_eq!(errs[0].kind, LedgerErrorKind::BalanceAssertion);
+        assert!(errs[0].message.contains(msg), "missing '{}' in '{}'", msg, errs[0].message);
+        assert_eq!(errs[0].pinfo.position.line, 7);
+    }
+
+    #[rstest]
+    #[case ("Assets:Cash", "Assets:Cash", true)]
+    #[case ("Assets:Cash:Petty", "Assets:Cash", true)]
+    #[case ("Assets:Cashier", "Assets:Cash", false)]
+    #[case ("Assets", "Assets:Cash", false)]
+    fn can_match_sub_account(#[case] acct: &str, #[case] parent: &str, #[case] expected: bool) {
+        assert_eq!(is_same_or_sub_account(acct, parent), expected);
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use log::info;
+
+use pta_types::*;
+
+use crate::ledger_error::*;
+
+
+// The tolerance within which a transaction's residual is considered zero. As in beancount, it is
+//  inferred per commodity from the precision of the amounts written in the transaction: a
+//  multiple of the last digit of the least precise amount, e.g., 0.005 for -400.00 USD.
+#[derive(Debug, Clone)]
+pub struct Tolerance {
+    pub multiplier: Decimal,
+    // Used when no tolerance can be inferred (integer amounts), keyed by commodity or "*" for any
+    pub defaults: BTreeMap<String, Decimal>,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        return Tolerance { multiplier: Decimal::new(5, 1), defaults: BTreeMap::new() };
+    }
+}
+
+impl Tolerance {
+    pub fn from_options(options: &LedgerOptions) -> Tolerance {
+        return Tolerance {
+            multiplier: options.inferred_tolerance_multiplier,
+            defaults: options.inferred_tolerance_default.clone(),
+        };
+    }
+
+    // The tolerance of each commodity in the transaction
+    pub fn infer(self: &Self, xn: &RawTransaction) -> BTreeMap<String, Decimal> {
+        let mut tols = BTreeMap::new();
+
+        // inferred amounts carry the precision of the sums, not of the ledger
+        for amount in xn.postings.iter().filter(|p| !p.inferred).filter_map(|p| p.amount.as_ref()) {
+            let scale = amount.number.scale();
+            if scale == 0 {
+                continue;
+            }
+
+            if let Some(tol) = Decimal::new(1, scale).checked_mul(&self.multiplier) {
+                let entry = tols.entry(amount.commodity.clone()).or_insert(tol);
+                if tol > *entry {
+                    *entry = tol;
+                }
+            }
+        }
+
+        return tols;
+    }
+
+    pub fn default_for(self: &Self, commodity: &str) -> Decimal {
+        return self.defaults.get(commodity)
+            .or_else(|| self.defaults.get("*"))
+            .copied()
+            .unwrap_or_default();
+    }
+}
+
+
+// The sum of the weights of the transaction's postings of the kind per commodity. None when a
+//  posting's weight cannot be known before interpolation or booking (i.e., an elided amount or a
+//  cost spec without an amount).
+pub fn residual(xn: &RawTransaction, kind: PostingKind) -> Result<Option<BTreeMap<String, Decimal>>, LedgerError> {
+    return residual_of(xn.postings.iter().filter(|p| p.kind == kind));
+}
+
+fn residual_of<'a>(postings: impl Iterator<Item = &'a RawPosting>) -> Result<Option<BTreeMap<String, Decimal>>, LedgerError> {
+    let mut sums: BTreeMap<String, Decimal> = BTreeMap::new();
+
+    for p in postings {
+        if p.amount.is_none() || p.cost.as_ref().is_some_and(|c| c.amount.is_none()) {
+            return Ok(None);
+        }
+
+        let overflow = || LedgerError::new(LedgerErrorKind::Overflow,
+            format!("weight of posting to {} overflows", p.acct.path), &p.pinfo);
+
+        let weight = p.weight().ok_or_else(overflow)?;
+        let sum = sums.entry(weight.commodity.clone()).or_default();
+        *sum = sum.checked_add(&weight.number).ok_or_else(overflow)?;
+    }
+
+    return Ok(Some(sums));
+}
+
+
+// Fills in the amount of a posting whose amount was elided, such that the transaction's postings
+//  of its kind balance. When the residual spans several commodities, the posting is split into
+//  one per commodity.
+pub fn infer_elided_amounts(xn: &mut RawTransaction) -> Result<(), LedgerError> {
+    let elided: Vec<usize> = xn.postings.iter().enumerate()
+        .filter(|(_, p)| p.amount.is_none())
+        .map(|(i, _)| i)
+        .collect();
+
+    match elided.len() {
+        0 => { return Ok(()); }
+        1 => {}
+        n => {
+            return Err(LedgerError::new(LedgerErrorKind::ElidedAmount,
+                format!("transaction has {n} postings without an amount; at most 1 may be elided"), &xn.pinfo));
+        }
+    }
+
+    let idx = elided[0];
+    let kind = xn.postings[idx].kind;
+    let sums = match residual_of(xn.postings.iter().filter(|p| p.amount.is_some() && p.kind == kind))? {
+        Some(sums) => sums,
+        None => {
+            info!("transaction at line {} has postings to be booked; leaving amount elided", xn.pinfo.position.line);
+            return Ok(());
+        }
+    };
+
+    if sums.is_empty() {
+        return Err(LedgerError::new(LedgerErrorKind::ElidedAmount,
+            "cannot infer the elided amount of a transaction without other postings".to_string(), &xn.postings[idx].pinfo));
+    }
+
+    // a zero residual still yields an amount so that the posting is kept
+    let mut residuals: Vec<(String, Decimal)> = sums.iter()
+        .filter(|(_, sum)| !sum.is_zero())
+        .map(|(c, sum)| (c.clone(), *sum))
+        .collect();
+    if residuals.is_empty() {
+        residuals = sums.into_iter().take(1).collect();
+    }
+
+    let template = xn.postings.remove(idx);
+    for (offset, (cmdty, sum)) in residuals.into_iter().enumerate() {
+        let number = sum.checked_neg().ok_or_else(|| LedgerError::new(LedgerErrorKind::Overflow,
+            format!("inferred amount of posting to {} overflows", template.acct.path), &template.pinfo))?;
+
+        let mut p = template.clone();
+        p.amount = Some(Amount::new(number, &cmdty));
+        p.inferred = true;
+        xn.postings.insert(idx + offset, p);
+    }
+
+    return Ok(());
+}
+
+
+pub fn check_transaction(xn: &RawTransaction, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    if xn.postings.len() < 2 {
+        errs.push(LedgerError::new(LedgerErrorKind::SinglePosting,
+            format!("transaction has {} posting(s); at least 2 are required", xn.postings.len()), &xn.pinfo));
+    }
+
+    // virtual postings need not balance; balanced virtual ones must balance among themselves
+    for kind in [PostingKind::Real, PostingKind::BalancedVirtual] {
+        match residual(xn, kind) {
+            Ok(Some(sums)) => { errs.extend(check_residual(xn, sums, tolerance)); }
+            Ok(None) => {
+                info!("transaction at line {} has postings to be booked; skipping balance check", xn.pinfo.position.line);
+            }
+            Err(e) => { errs.push(e); }
+        }
+    }
+
+    return errs;
+}
+
+fn check_residual(xn: &RawTransaction, sums: BTreeMap<String, Decimal>, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let tols = tolerance.infer(xn);
+    for (cmdty, sum) in sums {
+        let tol = tols.get(&cmdty).copied().unwrap_or_else(|| tolerance.default_for(&cmdty));
+        if sum.checked_abs().is_none_or(|abs| abs > tol) {
+            errs.push(LedgerError::new(LedgerErrorKind::Unbalanced,
+                format!("transaction does not balance: residual {} exceeds tolerance {}", Amount::new(sum, &cmdty), tol),
+                &xn.pinfo));
+        }
+    }
+
+    return errs;
+}
+
+// Checks that every transaction in the ledger has at least 2 postings and sums to zero, within
+//  tolerance, in each commodity
+pub fn check_transactions(pl: &ParsedLedger, tolerance: &Tolerance) -> Vec<LedgerError> {
+    return pl.transactions()
+        .flat_map(|xn| check_transaction(xn, tolerance))
+        .collect();
+}
+
+
+
+#[cfg(test)]
+mod balancing_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn check(ledger: &str) -> Vec<LedgerError> {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+        return check_transactions(pl, &Tolerance::default());
+    }
+
+    #[rstest]
+    #[case ("2009-01-09 ! \"balanced\"\n  Assets    1.0000\n  Equity    -1.0000\n")]
+    #[case ("2009-01-09 ! \"within tolerance\"\n  Assets    1.004 USD\n  Equity    -1.00 USD\n")]
+    #[case ("2016-01-28 * \"Buy BTC\"
+  Assets:Exchanges:Coinbase                     1.03683606 BTC { 381.9697397 USD, 2016-01-28 }
+  Assets:Bank:AllyChk                        -400.00 USD
+  Liabilities:Fees:Coinbase                     3.96 USD
+  Liabilities:Fees:Adjustment                   0.00000005 USD
+")]
+    #[case ("2016-01-28 * \"total cost\"\n  Assets:Coinbase    1.00 BTC {{400.00 USD}}\n  Assets:Bank    -400.00 USD\n")]
+    #[case ("2016-01-28 * \"price\"\n  Assets:EUR    -100.00 EUR @ 1.08 USD\n  Assets:USD    108.00 USD\n")]
+    #[case ("2016-01-28 * \"total price\"\n  Assets:EUR    -100.00 EUR @@ 108.00 USD\n  Assets:USD    108.00 USD\n")]
+    #[case ("2016-01-28 * \"any lot\"\n  Assets:Coinbase    -1.00 BTC {}\n  Assets:Bank    400.00 USD\n")]
+    #[case ("2016-01-28 * \"elided\"\n  Assets:Bank    -400.00 USD\n  Expenses:Food\n")]
+    fn can_balance_transaction(#[case] ledger: &str) {
+        let errs = check(ledger);
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("2009-01-09 ! \"unbalanced\"\n  Assets    1.0000\n  Equity    -1.0001\n", "residual -0.0001 exceeds tolerance 0.00005")]
+    #[case ("2009-01-09 ! \"outside tolerance\"\n  Assets    1.006 USD\n  Equity    -1.00 USD\n", "residual 0.006 USD exceeds tolerance 0.005")]
+    #[case ("2009-01-09 ! \"commodities\"\n  Assets    1.00 USD\n  Equity    -1.00 EUR\n", "residual -1.00 EUR")]
+    #[case ("2016-01-28 * \"price\"\n  Assets:EUR    -100.00 EUR @ 1.08 USD\n  Assets:USD    100.00 USD\n", "residual -8.0000 USD")]
+    fn verify_unbalanced_transaction(#[case] ledger: &str, #[case] msg: &str) {
+        let errs = check(ledger);
+        assert!(errs.iter().any(|e| e.kind == LedgerErrorKind::Unbalanced && e.message.contains(msg)), "missing '{}' in {:?}", msg, errs);
+        assert_eq!(errs[0].pinfo.position.line, 1);
+    }
+
+    #[rstest]
+    #[case ("2016-01-28 * \"elided\"\n  Assets:Bank    -400.00 USD\n  Expenses:Food  ; inferred\n", vec!["400.00 USD"])]
+    #[case ("2016-01-28 * \"first\"\n  Expenses:Food\n  Assets:Bank    -400.00 USD\n  Assets:Cash    -1.5 USD\n", vec!["401.50 USD"])]
+    #[case ("2016-01-28 * \"zero\"\n  Assets:Bank    -1.00 USD\n  Assets:Cash    1.00 USD\n  Equity\n", vec!["0.00 USD"])]
+    #[case ("2016-01-28 * \"cost\"\n  Assets:Coinbase    2.00 BTC {400.00 USD}\n  Assets:Bank\n", vec!["-800.0000 USD"])]
+    #[case ("2016-01-28 * \"price\"\n  Assets:EUR    -100.00 EUR @ 1.08 USD\n  Assets:USD\n", vec!["108.0000 USD"])]
+    #[case ("2016-01-28 * \"multi\"\n  Assets:EUR    -100.00 EUR\n  Assets:USD    50.00 USD\n  Equity:Conversions\n", vec!["100.00 EUR", "-50.00 USD"])]
+    fn can_infer_elided_amount(#[case] ledger: &str, #[case] inferred: Vec<&str>) {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+        let xn = pl.transactions().next().unwrap();
+
+        let amounts: Vec<String> = xn.postings.iter()
+            .filter(|p| p.inferred)
+            .map(|p| p.amount.as_ref().unwrap().to_string())
+            .collect();
+        assert_eq!(amounts, inferred);
+        assert!(check_transaction(xn, &Tolerance::default()).is_empty());
+    }
+
+    #[rstest]
+    #[case ("2016-01-28 * \"two elided\"\n  Assets:Bank    -400.00 USD\n  Expenses:Food\n  Expenses:Drink\n")]
+    #[case ("2016-01-28 * \"alone\"\n  Expenses:Food\n")]
+    fn verify_elided_amount_error(#[case] ledger: &str) {
+        let mut bldr = LedgerBuilder::default();
+        let err = bldr.from_string(ledger).expect_err("elided amount should not be inferred");
+        let err = err.downcast_ref::<LedgerError>().expect("should be a LedgerError");
+        assert_eq!(err.kind, LedgerErrorKind::ElidedAmount);
+    }
+
+    #[test]
+    fn verify_basic_ledger_single_posting() {
+        let ledger = std::fs::read_to_string("../testdata/basic-ledger")
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = check(&ledger);
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].kind, LedgerErrorKind::SinglePosting);
+        assert_eq!(errs[0].pinfo.position.line, 30);
+    }
+
+    #[test]
+    fn can_configure_tolerance() {
+        let xn = "2009-01-09 ! \"tolerance\"\n  Assets    1.0000 USD\n  Equity    -1.0000 USD\n";
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(xn).unwrap_or_else(|e| panic!("{}", e));
+        let xn = pl.transactions().next().unwrap();
+
+        let mut tolerance = Tolerance::default();
+        assert_eq!(tolerance.infer(xn).get("USD").unwrap().to_string(), "0.00005");
+        assert!(tolerance.default_for("USD").is_zero());
+
+        tolerance.defaults.insert("*".to_string(), "0.01".parse().unwrap());
+        tolerance.defaults.insert("EUR".to_string(), "0.005".parse().unwrap());
+        assert_eq!(tolerance.default_for("USD").to_string(), "0.01");
+        assert_eq!(tolerance.default_for("EUR").to_string(), "0.005");
+    }
+}
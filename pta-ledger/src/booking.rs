@@ -0,0 +1,375 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use pta_types::*;
+
+use crate::balancing;
+use crate::inventory::*;
+use crate::ledger_error::*;
+
+
+// The gain (or loss, when negative) realised by reducing lots, in the currency of their cost.
+//  Proceeds, and thus the gain, are only known when the reducing posting has a price.
+#[derive(Debug, Clone)]
+pub struct RealizedGain {
+    pub date: String,
+    pub acct: String,
+    pub units: Amount,
+    pub cost_basis: Amount,
+    pub proceeds: Option<Amount>,
+    pub gain: Option<Amount>,
+    pub pinfo: ParserInfo,
+}
+
+// The outcome of booking a ledger: the final inventory of each account, the gains realised
+//  along the way and any errors found
+#[derive(Debug, Default)]
+pub struct BookingResult {
+    pub inventories: BTreeMap<String, Inventory>,
+    pub gains: Vec<RealizedGain>,
+    pub errors: Vec<LedgerError>,
+}
+
+
+// Books the ledger's transactions in date order, maintaining an inventory of lots per account.
+//  Postings at cost which reduce an account's lots are matched by the account's booking method
+//  (or 'default_method' if its open directive names none) and replaced by one posting per lot
+//  reduced, at that lot's cost. Amounts left elided pending booking are then inferred.
+pub fn book(pl: &mut ParsedLedger, default_method: BookingMethod) -> BookingResult {
+    let mut methods: BTreeMap<String, BookingMethod> = BTreeMap::new();
+    for d in pl.directives() {
+        if let RawDirective::Open(open) = d {
+            methods.entry(open.acct.path.clone()).or_insert(open.booking.unwrap_or(default_method));
+        }
+    }
+
+    let mut result = BookingResult::default();
+
+    let mut xns: Vec<&mut RawTransaction> = pl.transactions_mut().collect();
+    xns.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let method_of = |acct: &str| methods.get(acct).copied().unwrap_or(default_method);
+    for xn in xns {
+        book_transaction(xn, &method_of, &mut result);
+    }
+
+    return result;
+}
+
+fn book_transaction(xn: &mut RawTransaction, method_of: &dyn Fn(&str) -> BookingMethod, result: &mut BookingResult) {
+    let mut booked = Vec::with_capacity(xn.postings.len());
+
+    for p in std::mem::take(&mut xn.postings) {
+        let (Some(units), Some(_)) = (&p.amount, &p.cost) else {
+            booked.push(p);
+            continue;
+        };
+
+        let inv = result.inventories.entry(p.acct.path.clone()).or_default();
+        let method = method_of(&p.acct.path);
+        let is_reduction = method != BookingMethod::None
+            && inv.lots_of(&units.commodity).any(|l| l.units.number.is_negative() != units.number.is_negative());
+
+        let outcome = if is_reduction {
+            reduce(&xn.date, &p, inv, method)
+        } else {
+            augment(&xn.date, &p, inv).map(|()| (vec![p.clone()], None))
+        };
+
+        match outcome {
+            Ok((postings, gain)) => {
+                booked.extend(postings);
+                result.gains.extend(gain);
+            }
+            Err(e) => {
+                result.errors.push(e);
+                booked.push(p);
+            }
+        }
+    }
+
+    xn.postings = booked;
+
+    if let Err(e) = balancing::infer_elided_amounts(xn) {
+        result.errors.push(e);
+    }
+
+    // postings not held at cost simply accumulate units
+    for p in xn.postings.iter().filter(|p| p.cost.is_none()) {
+        if let Some(units) = &p.amount {
+            let inv = result.inventories.entry(p.acct.path.clone()).or_default();
+            if inv.add(units, None).is_none() {
+                result.errors.push(LedgerError::new(LedgerErrorKind::Overflow,
+                    format!("inventory of {} overflows", p.acct.path), &p.pinfo));
+            }
+        }
+    }
+}
+
+fn overflow(p: &RawPosting) -> LedgerError {
+    return LedgerError::new(LedgerErrorKind::Overflow, format!("inventory of {} overflows", p.acct.path), &p.pinfo);
+}
+
+// Adds a new lot, dated by the cost spec or else the transaction
+fn augment(date: &str, p: &RawPosting, inv: &mut Inventory) -> Result<(), LedgerError> {
+    let (Some(units), Some(cost)) = (&p.amount, &p.cost) else {
+        return Ok(());
+    };
+
+    let Some(amount) = &cost.amount else {
+        return Err(LedgerError::new(LedgerErrorKind::MissingCost,
+            format!("cost of {} added to {} must be specified", units, p.acct.path), &p.pinfo));
+    };
+
+    let number = match cost.kind {
+        CostKind::PerUnit => amount.number,
+        CostKind::Total => LotCost::per_unit(&amount.number, &units.number).ok_or_else(|| overflow(p))?,
+    };
+
+    let lot = LotCost {
+        number,
+        currency: amount.commodity.clone(),
+        date: cost.date.clone().unwrap_or_else(|| date.to_string()),
+        label: cost.label.clone(),
+    };
+
+    return inv.add(units, Some(&lot)).ok_or_else(|| overflow(p));
+}
+
+// Whether the lot satisfies each component given in the cost spec
+fn matches_spec(lot: &LotCost, spec: &Cost, units: &Decimal) -> bool {
+    if let Some(amount) = &spec.amount {
+        let number = match spec.kind {
+            CostKind::PerUnit => Some(amount.number),
+            CostKind::Total => LotCost::per_unit(&amount.number, units),
+        };
+
+        if amount.commodity != lot.currency || number != Some(lot.number) {
+            return false;
+        }
+    }
+
+    return spec.date.as_ref().is_none_or(|d| *d == lot.date)
+        && spec.label.as_ref().is_none_or(|l| Some(l) == lot.label.as_ref());
+}
+
+// Reduces the lots matched by the posting's cost spec, returning a posting per lot reduced along
+//  with the realised gain
+fn reduce(date: &str, p: &RawPosting, inv: &mut Inventory, method: BookingMethod) -> Result<(Vec<RawPosting>, Option<RealizedGain>), LedgerError> {
+    let (Some(units), Some(spec)) = (&p.amount, &p.cost) else {
+        return Ok((vec![p.clone()], None));
+    };
+
+    if method == BookingMethod::Average {
+        inv.average_lots(&units.commodity).ok_or_else(|| LedgerError::new(LedgerErrorKind::Overflow,
+            format!("cannot average the lots of {} in {}", units.commodity, p.acct.path), &p.pinfo))?;
+    }
+
+    let mut matched: Vec<(Decimal, LotCost)> = inv.lots_of(&units.commodity)
+        .filter(|l| l.units.number.is_negative() != units.number.is_negative())
+        .filter_map(|l| l.cost.as_ref().map(|c| (l.units.number.checked_abs(), c.clone())))
+        .filter(|(_, c)| method == BookingMethod::Average || matches_spec(c, spec, &units.number))
+        .map(|(n, c)| Some((n?, c)))
+        .collect::<Option<Vec<(Decimal, LotCost)>>>()
+        .ok_or_else(|| overflow(p))?;
+
+    if matched.is_empty() {
+        return Err(LedgerError::new(LedgerErrorKind::NoMatchingLot,
+            format!("no lot of {} in {} matches the reduction of {}", units.commodity, p.acct.path, units), &p.pinfo));
+    }
+
+    let wanted = units.number.checked_abs().ok_or_else(|| overflow(p))?;
+    let mut held = Decimal::zero();
+    for (n, _) in &matched {
+        held = held.checked_add(n).ok_or_else(|| overflow(p))?;
+    }
+
+    match method {
+        BookingMethod::Strict | BookingMethod::StrictWithSize if matched.len() > 1 && held != wanted => {
+            let sized = matched.iter()
+                .filter(|(n, _)| *n == wanted)
+                .min_by(|a, b| a.1.date.cmp(&b.1.date))
+                .cloned();
+
+            match sized {
+                Some(lot) if method == BookingMethod::StrictWithSize => { matched = vec![lot]; }
+                _ => {
+                    return Err(LedgerError::new(LedgerErrorKind::AmbiguousLotMatch,
+                        format!("ambiguous reduction of {} from {}; {} lots match: {}", units, p.acct.path, matched.len(),
+                            matched.iter().map(|(_, c)| c.to_string()).collect::<Vec<_>>().join(", ")),
+                        &p.pinfo));
+                }
+            }
+        }
+        BookingMethod::Fifo => { matched.sort_by(|a, b| a.1.date.cmp(&b.1.date)); }
+        BookingMethod::Lifo => { matched.sort_by(|a, b| b.1.date.cmp(&a.1.date)); }
+        BookingMethod::Hifo => { matched.sort_by_key(|l| std::cmp::Reverse(l.1.number)); }
+        _ => {}
+    }
+
+    if held < wanted {
+        return Err(LedgerError::new(LedgerErrorKind::InsufficientLots,
+            format!("reduction of {} from {} exceeds the {} {} held in matching lots", units, p.acct.path, held, units.commodity),
+            &p.pinfo));
+    }
+
+    let mut postings = Vec::new();
+    let mut remaining = wanted;
+    let mut basis = Decimal::zero();
+    for (n, cost) in matched {
+        if remaining.is_zero() {
+            break;
+        }
+
+        let take = remaining.min(n);
+        remaining = remaining.checked_sub(&take).ok_or_else(|| overflow(p))?;
+
+        let number = if units.number.is_negative() { take.checked_neg().ok_or_else(|| overflow(p))? } else { take };
+        let reduced = Amount::new(number, &units.commodity);
+        inv.add(&reduced, Some(&cost)).ok_or_else(|| overflow(p))?;
+        basis = basis.checked_add(&number.checked_mul(&cost.number).ok_or_else(|| overflow(p))?).ok_or_else(|| overflow(p))?;
+
+        let mut booked = p.clone();
+        booked.amount = Some(reduced);
+        booked.cost = Some(Cost {
+            kind: CostKind::PerUnit,
+            amount: Some(Amount::new(cost.number, &cost.currency)),
+            date: Some(cost.date.clone()),
+            label: cost.label.clone(),
+            pinfo: spec.pinfo.clone(),
+        });
+        postings.push(booked);
+    }
+
+    // lots matched by reduction share the cost currency of the first
+    let currency = postings.first()
+        .and_then(|b| b.cost.as_ref())
+        .and_then(|c| c.amount.as_ref())
+        .map(|a| a.commodity.clone())
+        .unwrap_or_default();
+
+    let proceeds = p.price.as_ref()
+        .filter(|pr| pr.amount.commodity == currency)
+        .and_then(|pr| pr.converted(&units.number));
+    let gain = match &proceeds {
+        Some(proceeds) => Some(Amount::new(basis.checked_sub(&proceeds.number).ok_or_else(|| overflow(p))?, &currency)),
+        None => None,
+    };
+
+    let realized = RealizedGain {
+        date: date.to_string(),
+        acct: p.acct.path.clone(),
+        units: units.clone(),
+        cost_basis: Amount::new(basis, &currency),
+        proceeds,
+        gain,
+        pinfo: p.pinfo.clone(),
+    };
+
+    return Ok((postings, Some(realized)));
+}
+
+
+
+#[cfg(test)]
+mod booking_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+    use crate::validation::{validate, ValidationOptions};
+
+    const LOTS: &str = "2001-09-11 open Assets:Broker:BTC BTC \"METHOD\"
+2001-09-11 open Assets:Broker:Cash
+2001-09-11 open Income:Gains
+2001-09-12 * \"buy\"
+  Assets:Broker:BTC    1.0 BTC {300.00 USD}
+  Assets:Broker:Cash
+2001-09-13 * \"buy\"
+  Assets:Broker:BTC    1.0 BTC {500.00 USD, \"second\"}
+  Assets:Broker:Cash
+2001-09-14 * \"buy\"
+  Assets:Broker:BTC    2.0 BTC {{800.00 USD}}
+  Assets:Broker:Cash
+";
+
+    fn book_with(method: &str, sale: &str) -> (BookingResult, Vec<LedgerError>) {
+        let mut bldr = LedgerBuilder::default();
+        let ledger = format!("{}{}", LOTS.replace("METHOD", method), sale);
+        let pl = bldr.from_string(&ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        let result = book(pl, BookingMethod::default());
+        let errs = validate(pl, &ValidationOptions::default());
+        return (result, errs);
+    }
+
+    fn sale(units: &str, spec: &str) -> String {
+        let units: Decimal = units.parse().unwrap_or_else(|e| panic!("{}", e));
+        let cash = units.checked_mul(&Decimal::new(-60000, 2)).unwrap();
+        return format!("2001-09-15 * \"sell\"\n  Assets:Broker:BTC    {units} BTC {spec} @ 600.00 USD\n  Assets:Broker:Cash    {cash} USD\n  Income:Gains\n");
+    }
+
+    #[rstest]
+    #[case ("FIFO", "-1.5", "{}", "-550", "350")]
+    #[case ("LIFO", "-1.5", "{}", "-600", "300")]
+    #[case ("HIFO", "-1.5", "{}", "-700", "200")]
+    #[case ("AVERAGE", "-1.0", "{}", "-400", "200")]
+    #[case ("STRICT", "-1.0", "{300.00 USD}", "-300.00", "300.00")]
+    #[case ("STRICT", "-1.0", "{\"second\"}", "-500.00", "100.00")]
+    #[case ("STRICT", "-1.0", "{2001-09-12}", "-300.00", "300.00")]
+    #[case ("STRICT", "-4.0", "{}", "-1600.00", "800.00")]
+    #[case ("STRICT_WITH_SIZE", "-2.0", "{}", "-800.00", "400.00")]
+    fn can_book_reduction(#[case] method: &str, #[case] units: &str, #[case] spec: &str, #[case] basis: &str, #[case] gain: &str) {
+        let (result, errs) = book_with(method, &sale(units, spec));
+        assert!(result.errors.is_empty(), "unexpected booking errors: {:?}", result.errors);
+        assert!(errs.is_empty(), "unexpected validation errors: {:?}", errs);
+
+        assert_eq!(result.gains.len(), 1);
+        let realized = &result.gains[0];
+        assert_eq!(realized.cost_basis, Amount::new(basis.parse().unwrap(), "USD"));
+        assert_eq!(realized.gain, Some(Amount::new(gain.parse().unwrap(), "USD")));
+    }
+
+    #[rstest]
+    #[case ("FIFO", "-1.5", &["0.5 BTC {500.00 USD, 2001-09-13, \"second\"}", "2.0 BTC {400.000 USD, 2001-09-14}"])]
+    #[case ("LIFO", "-2.5", &["1.0 BTC {300.00 USD, 2001-09-12}", "0.5 BTC {500.00 USD, 2001-09-13, \"second\"}"])]
+    #[case ("AVERAGE", "-1.0", &["3.0 BTC {400.00000 USD, 2001-09-12}"])]
+    fn can_reduce_inventory(#[case] method: &str, #[case] units: &str, #[case] expected: &[&str]) {
+        let (result, _) = book_with(method, &sale(units, "{}"));
+        assert!(result.errors.is_empty(), "unexpected booking errors: {:?}", result.errors);
+
+        let inv = &result.inventories["Assets:Broker:BTC"];
+        let positions: Vec<String> = inv.positions().map(|p| p.to_string()).collect();
+        assert_eq!(positions, expected);
+    }
+
+    #[test]
+    fn can_book_without_reductions() {
+        let (result, _) = book_with("NONE", &sale("-1.0", "{}"));
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, LedgerErrorKind::MissingCost);
+
+        let (result, _) = book_with("NONE", &sale("-1.0", "{600.00 USD}"));
+        assert!(result.errors.is_empty(), "unexpected booking errors: {:?}", result.errors);
+        assert_eq!(result.inventories["Assets:Broker:BTC"].lots_of("BTC").count(), 4);
+        assert!(result.gains.is_empty());
+    }
+
+    #[rstest]
+    #[case ("STRICT", "-1.0", "{}", LedgerErrorKind::AmbiguousLotMatch, "3 lots match")]
+    #[case ("STRICT_WITH_SIZE", "-0.5", "{}", LedgerErrorKind::AmbiguousLotMatch, "3 lots match")]
+    #[case ("STRICT", "-1.0", "{301.00 USD}", LedgerErrorKind::NoMatchingLot, "no lot of BTC")]
+    #[case ("FIFO", "-5.0", "{}", LedgerErrorKind::InsufficientLots, "exceeds the 4.0 BTC held")]
+    #[case ("STRICT", "-2.0", "{\"second\"}", LedgerErrorKind::InsufficientLots, "exceeds the 1.0 BTC held")]
+    fn verify_booking_error(#[case] method: &str, #[case] units: &str, #[case] spec: &str, #[case] kind: LedgerErrorKind, #[case] msg: &str) {
+        let (result, _) = book_with(method, &sale(units, spec));
+        assert_eq!(result.errors.len(), 1, "expected a single error: {:?}", result.errors);
+        assert_eq!(result.errors[0].kind, kind);
+        assert_eq!(result.errors[0].pinfo.position.line, 14);
+        assert!(result.errors[0].message.contains(msg), "missing '{}' in '{}'", msg, result.errors[0].message);
+    }
+}
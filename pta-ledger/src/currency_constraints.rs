@@ -0,0 +1,87 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use pta_types::*;
+
+use crate::ledger_error::*;
+
+
+// Checks that postings to accounts opened with constraint currencies use only those currencies
+pub fn check_currency_constraints(pl: &ParsedLedger) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let mut constraints: BTreeMap<&str, &Vec<String>> = BTreeMap::new();
+    for d in pl.directives() {
+        if let RawDirective::Open(open) = d {
+            if !open.currencies.is_empty() {
+                constraints.entry(&open.acct.path).or_insert(&open.currencies);
+            }
+        }
+    }
+
+    for p in pl.transactions().flat_map(|xn| xn.postings.iter()) {
+        let Some(allowed) = constraints.get(p.acct.path.as_str()) else {
+            continue;
+        };
+
+        match p.commodity() {
+            Some(cmdty) if allowed.iter().any(|c| c == cmdty) => {}
+            Some(cmdty) => {
+                errs.push(LedgerError::new(LedgerErrorKind::CurrencyConstraint,
+                    format!("posting in {} to account {} which only allows {}", cmdty, p.acct.path, allowed.join(", ")),
+                    &p.pinfo));
+            }
+            None => {
+                errs.push(LedgerError::new(LedgerErrorKind::CurrencyConstraint,
+                    format!("posting without a currency to account {} which only allows {}", p.acct.path, allowed.join(", ")),
+                    &p.pinfo));
+            }
+        }
+    }
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod currency_constraints_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn check(ledger: &str) -> Vec<LedgerError> {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+        return check_currency_constraints(pl);
+    }
+
+    const OPENS: &str = "2001-09-11 open Assets:Cash USD,EUR \"FIFO\"
+2001-09-11 open Equity
+";
+
+    #[rstest]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Cash    1.00 USD\n  Equity\n")]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Cash    1.00 EUR\n  Equity    -1.00 EUR\n")]
+    #[case ("2001-09-12 * \"x\"\n  Equity    1.00 BTC\n  Equity    -1.00 BTC\n")]    // unconstrained
+    fn can_check_currency_constraints(#[case] ledger: &str) {
+        let errs = check(&format!("{OPENS}{ledger}"));
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Cash    1.00 BTC\n  Equity\n", "posting in BTC to account Assets:Cash which only allows USD, EUR")]
+    #[case ("2001-09-12 * \"x\"\n  Assets:Cash    1.00\n  Equity\n", "posting without a currency to account Assets:Cash")]
+    fn verify_currency_constraint_error(#[case] ledger: &str, #[case] msg: &str) {
+        let errs = check(&format!("{OPENS}{ledger}"));
+        assert_eq!(errs.len(), 1, "expected a single error: {:?}", errs);
+        assert_eq!(errs[0].kind, LedgerErrorKind::CurrencyConstraint);
+        assert_eq!(errs[0].pinfo.position.line, 4);
+        assert!(errs[0].message.contains(msg), "missing '{}' in '{}'", msg, errs[0].message);
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::fmt;
+
+use pta_types::*;
+
+
+// The per-unit cost of a lot along with its acquisition date and optional label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LotCost {
+    pub number: Decimal,
+    pub currency: String,
+    pub date: String,
+    pub label: Option<String>,
+}
+
+impl LotCost {
+    // The per-unit cost of the given total cost of units. The quotient keeps the precision of both
+    //  the total and the units, so that multiplying back out is accurate to the total's precision.
+    pub fn per_unit(total: &Decimal, units: &Decimal) -> Option<Decimal> {
+        return total.checked_abs()?.checked_div(&units.checked_abs()?, total.scale() + units.scale());
+    }
+}
+
+impl fmt::Display for LotCost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{} {}, {}", self.number, self.currency, self.date)?;
+        if let Some(label) = &self.label {
+            write!(f, ", \"{label}\"")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+
+// Units of a commodity, held at cost when acquired as a lot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub units: Amount,
+    pub cost: Option<LotCost>,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.cost {
+            Some(cost) => write!(f, "{} {}", self.units, cost),
+            None => write!(f, "{}", self.units),
+        }
+    }
+}
+
+
+// The positions held by an account. Units of the same commodity and cost are merged, and
+//  positions reduced to zero are removed.
+#[derive(Debug, Default, Clone)]
+pub struct Inventory {
+    positions: Vec<Position>,
+}
+
+impl Inventory {
+    pub fn positions(self: &Self) -> std::slice::Iter<'_, Position> {
+        return self.positions.iter();
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        return self.positions.is_empty();
+    }
+
+    // The total units of the commodity, across all lots
+    pub fn units_of(self: &Self, commodity: &str) -> Option<Decimal> {
+        let mut total = Decimal::zero();
+        for p in self.positions.iter().filter(|p| p.units.commodity == commodity) {
+            total = total.checked_add(&p.units.number)?;
+        }
+
+        return Some(total);
+    }
+
+    // The lots (positions held at cost) of the commodity
+    pub fn lots_of<'a>(self: &'a Self, commodity: &'a str) -> impl Iterator<Item = &'a Position> + 'a {
+        return self.positions.iter()
+            .filter(move |p| p.units.commodity == commodity && p.cost.is_some());
+    }
+
+    // Adds (signed) units to the position of the same commodity and cost, or as a new position;
+    //  None on overflow. A lot is reduced by adding negative units at its cost.
+    pub fn add(self: &mut Self, units: &Amount, cost: Option<&LotCost>) -> Option<()> {
+        match self.positions.iter_mut().find(|p| p.units.commodity == units.commodity && p.cost.as_ref() == cost) {
+            Some(p) => { p.units.number = p.units.number.checked_add(&units.number)?; }
+            None => { self.positions.push(Position { units: units.clone(), cost: cost.cloned() }); }
+        }
+
+        self.positions.retain(|p| !p.units.is_zero());
+        return Some(());
+    }
+
+    // Replaces the lots of the commodity with a single lot at their average cost; None on overflow
+    //  or when the lots are held in more than one cost currency
+    pub fn average_lots(self: &mut Self, commodity: &str) -> Option<()> {
+        let lots: Vec<Position> = self.lots_of(commodity).cloned().collect();
+        if lots.len() < 2 {
+            return Some(());
+        }
+
+        let first = lots[0].cost.clone()?;
+        let mut units = Decimal::zero();
+        let mut total = Decimal::zero();
+        let mut date = first.date.clone();
+        for lot in &lots {
+            let cost = lot.cost.as_ref()?;
+            if cost.currency != first.currency {
+                return None;
+            }
+
+            units = units.checked_add(&lot.units.number)?;
+            total = total.checked_add(&lot.units.number.checked_mul(&cost.number)?)?;
+            date = date.min(cost.date.clone());
+        }
+
+        self.positions.retain(|p| p.units.commodity != commodity || p.cost.is_none());
+        if !units.is_zero() {
+            let cost = LotCost { number: LotCost::per_unit(&total, &units)?, currency: first.currency, date, label: None };
+            self.positions.push(Position { units: Amount::new(units, commodity), cost: Some(cost) });
+        }
+
+        return Some(());
+    }
+}
@@ -4,100 +4,434 @@
 //
 
 
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
 use log::{info, warn};
 
 use pta_types::*;
 
+use crate::balancing;
+use crate::ledger_error::*;
+
+mod hledger_journal;
+mod journal;
+mod ledger_cli;
+
 
 
 // TODO: how to isolate pest so clients can just use lib (w/o requiring pest as here)
-use pest::{*, iterators::Pair};
-use pta_parser::parsers::generic;
+use pest::{*, iterators::{Pair, Pairs}};
+use pta_parser::parsers::{beancount, generic, Dialect};
 
 #[derive(Default)]
 pub struct LedgerBuilder {
-    pl: ParsedLedger
+    pl: ParsedLedger,
+    // The dialect of every file, or None to detect the dialect of each
+    dialect: Option<Dialect>,
+    // The dialect of the string or root file last built
+    root_dialect: Dialect,
+    // The files being loaded, innermost last; used to resolve includes and detect cycles
+    file_stack: Vec<PathBuf>,
+    loaded: BTreeSet<PathBuf>,
+    // The source being parsed and, unless it is a string, its file
+    source: SourceId,
+    file: Option<Arc<Path>>,
+    // The tags pushed in the file being parsed, with the pushtag which pushed each
+    tag_stack: Vec<(String, ParserInfo)>,
 }
 
 impl LedgerBuilder {
-    pub fn from_string(self: &mut Self, ledger: &String) -> Result<&mut ParsedLedger, Box<dyn std::error::Error>> {
+    // A builder which parses everything in the dialect rather than detecting it
+    pub fn with_dialect(dialect: Dialect) -> LedgerBuilder {
+        return LedgerBuilder { dialect: Some(dialect), ..Default::default() };
+    }
+
+    // The dialect, given or detected, of the string or root file last built
+    pub fn dialect(self: &Self) -> Dialect {
+        return self.root_dialect;
+    }
+
+    // Builds a ledger from a string; any includes are resolved relative to the current directory
+    pub fn from_string(self: &mut Self, ledger: &str) -> Result<&mut ParsedLedger, Box<dyn std::error::Error>> {
+
+        self.reset();
+        self.source = self.pl.add_source(None, &ParserInfo::default());
+        let dialect = self.dialect.unwrap_or_else(|| Dialect::detect(None, ledger));
+        self.root_dialect = dialect;
+        self.parse_ledger(ledger, dialect)?;
+
+        return Ok(&mut self.pl);
+    }
 
+    // Builds a ledger from a file along with the files it includes
+    pub fn from_path(self: &mut Self, path: &Path) -> Result<&mut ParsedLedger, Box<dyn std::error::Error>> {
+
+        self.reset();
+        self.load_file(path, &ParserInfo::default())?;
+
+        return Ok(&mut self.pl);
+    }
+
+
+    fn reset(self: &mut Self) {
         self.pl = ParsedLedger::default();
+        self.file_stack.clear();
+        self.loaded.clear();
+        self.source = SourceId::default();
+        self.file = None;
+        self.tag_stack.clear();
+    }
+
+    // Every dialect but the journal ones of ledger-cli and hledger (cf. journal) is built from its
+    //  own parse by the handlers of generic rules
+    fn parse_ledger(self: &mut Self, ledger: &str, dialect: Dialect) -> Result<(), Box<dyn std::error::Error>> {
+        info!("parsing as {dialect}");
+        match dialect {
+            Dialect::Ledger => { return self.parse_journal::<ledger_cli::LedgerCli>(ledger); }
+            Dialect::Hledger => { return self.parse_journal::<hledger_journal::Hledger>(ledger); }
+            Dialect::Beancount => {
+                let root = beancount::Parser::parse(beancount::Rule::beancount_ledger, ledger);
+                return self.build_ledger(root);
+            }
+            Dialect::Generic => {
+                let root = generic::Parser::parse(generic::Rule::generic_ledger, ledger);
+                return self.build_ledger(root);
+            }
+        }
+    }
 
-        match generic::Parser::parse(generic::Rule::generic_ledger, &ledger) {
+    fn build_ledger<R: LedgerRule + Send + Sync + 'static>(self: &mut Self, root: Result<Pairs<R>, pest::error::Error<R>>) -> Result<(), Box<dyn std::error::Error>> {
+        match root {
             Ok(root) => {
-                info!("Successfully parsed with generic::Rule::generic_ledger");
+                info!("Successfully parsed the ledger");
                 for pair in root.into_iter() {
-                    info!("LedgerBuilder::from_string: root pair is {:}", pair.as_str());
+                    info!("LedgerBuilder::build_ledger: root pair is {:}", pair.as_str());
                     self.handle_pair(pair)?;
-                } 
+                }
+
+                if let Some((tag, pinfo)) = self.tag_stack.last() {
+                    return Err(Box::new(LedgerError::new(LedgerErrorKind::UnbalancedTag,
+                        format!("tag #{tag} is pushed but never popped"), pinfo)));
+                }
             }
-    
+
             Err(err) => {
-                warn!("failed to parse with generic::Rule::generic_ledger. err: {err}");
-                return Err(Box::new(err));
+                warn!("failed to parse the ledger. err: {err}");
+                return Err(self.with_file(err));
             }
         }
-    
-        return Ok(&mut self.pl);
+
+        return Ok(());
+    }
+
+    // The parse error, naming the file being parsed if any
+    fn with_file<R: RuleType + Send + Sync + 'static>(self: &Self, err: pest::error::Error<R>) -> Box<dyn std::error::Error> {
+        return match self.file_stack.last() {
+            Some(file) => Box::new(err.with_path(&file.to_string_lossy())),
+            None => Box::new(err),
+        };
+    }
+
+    // Loads the file unless it was already loaded; 'pinfo' locates the include which named it
+    fn load_file(self: &mut Self, path: &Path, pinfo: &ParserInfo) -> Result<(), Box<dyn std::error::Error>> {
+        let file = path.canonicalize().map_err(|e| LedgerError::new(LedgerErrorKind::IncludeNotFound,
+            format!("cannot read {}: {e}", path.display()), pinfo))?;
+
+        if self.file_stack.contains(&file) {
+            let chain: Vec<String> = self.file_stack.iter().chain([&file]).map(|f| f.display().to_string()).collect();
+            return Err(Box::new(LedgerError::new(LedgerErrorKind::IncludeCycle,
+                format!("include cycle: {}", chain.join(" -> ")), pinfo)));
+        }
+
+        if !self.loaded.insert(file.clone()) {
+            warn!("skipping {}; it was already included", file.display());
+            return Ok(());
+        }
+
+        info!("loading {}", file.display());
+        let ledger = std::fs::read_to_string(&file)?;
+
+        let shared: Arc<Path> = Arc::from(file.as_path());
+        // each file has its own tag stack
+        let outer = (self.source, self.file.replace(shared.clone()), std::mem::take(&mut self.tag_stack));
+        self.source = self.pl.add_source(Some(shared), pinfo);
+
+        let dialect = self.dialect.unwrap_or_else(|| Dialect::detect(Some(&file), &ledger));
+        if self.file_stack.is_empty() {
+            self.root_dialect = dialect;
+        }
+        self.file_stack.push(file);
+        let result = self.parse_ledger(&ledger, dialect);
+        self.file_stack.pop();
+
+        (self.source, self.file, self.tag_stack) = outer;
+        return result;
     }
 
+    fn handle_directive_include<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        info!("handling directive_include...");
 
-    fn handle_pair(self: &Self, pair: Pair<'_, generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut pinfo = parser_info(pair);
+        self.stamp_source(|f| f(&mut pinfo));
 
-        match pair.as_rule() {
+        let include = pair.clone().into_inner()
+            .find(|inner| rule_of(inner) == generic::Rule::include_path)
+            .map(|inner| inner.as_str())
+            .unwrap_or_default();
+
+        let pattern = self.base_dir()?.join(include);
+        let mut paths = glob::glob(&pattern.to_string_lossy())?.collect::<Result<Vec<PathBuf>, _>>()?;
+        if paths.is_empty() {
+            return Err(Box::new(LedgerError::new(LedgerErrorKind::IncludeNotFound,
+                format!("include \"{include}\" matches no files"), &pinfo)));
+        }
+
+        paths.sort();
+        for path in paths {
+            self.load_file(&path, &pinfo)?;
+        }
+
+        return Ok(());
+    }
+
+    fn handle_directive_document<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut doc = handle_directive_document(pair)?;
+        doc.resolved = self.base_dir()?.join(&doc.path);
+        self.add_directive(RawDirective::Document(doc));
+        return Ok(());
+    }
+
+    // The directory relative paths are resolved against: that of the file being parsed, or the
+    //  current directory for a ledger given as a string
+    fn base_dir(self: &Self) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        match self.file_stack.last().and_then(|f| f.parent()) {
+            Some(dir) => { return Ok(dir.to_path_buf()); }
+            None => { return Ok(std::env::current_dir()?); }
+        }
+    }
+
+    fn handle_directive_option<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        info!("handling directive_option...");
+
+        let mut pinfo = parser_info(pair);
+        self.stamp_source(|f| f(&mut pinfo));
+
+        let mut name = "";
+        let mut value = "";
+        for inner in pair.clone().into_inner() {
+            match rule_of(&inner) {
+                generic::Rule::option_name => { name = inner.as_str(); }
+                generic::Rule::option_value => { value = inner.as_str(); }
+                _ => { dump_pair(&inner); }
+            }
+        }
+
+        return self.pl.options_mut().set(name, value)
+            .map_err(|e| LedgerError::new(LedgerErrorKind::InvalidOption, e, &pinfo).into());
+    }
+
+    fn handle_directive_pushtag<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        info!("handling directive_pushtag...");
+
+        let mut pinfo = parser_info(pair);
+        self.stamp_source(|f| f(&mut pinfo));
+
+        self.tag_stack.push((name_of(pair), pinfo));
+        return Ok(());
+    }
+
+    // Pops the most recent push of the tag, which need not be the last tag pushed
+    fn handle_directive_poptag<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        info!("handling directive_poptag...");
+
+        let mut pinfo = parser_info(pair);
+        self.stamp_source(|f| f(&mut pinfo));
+
+        let tag = name_of(pair);
+        match self.tag_stack.iter().rposition(|(t, _)| *t == tag) {
+            Some(i) => { self.tag_stack.remove(i); }
+            None => {
+                return Err(Box::new(LedgerError::new(LedgerErrorKind::UnbalancedTag,
+                    format!("tag #{tag} is popped but was not pushed"), &pinfo)));
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Records the source being parsed in the parser info of everything within the item
+    fn stamp_source(self: &Self, pinfos: impl FnOnce(&mut dyn FnMut(&mut ParserInfo))) {
+        pinfos(&mut |pinfo| {
+            pinfo.source = self.source;
+            pinfo.file = self.file.clone();
+        });
+    }
+
+    fn add_directive(self: &mut Self, mut directive: RawDirective) {
+        self.stamp_source(|f| directive.for_each_pinfo_mut(f));
+        self.pl.add_directive(directive);
+    }
+
+
+    fn handle_pair<R: LedgerRule>(self: &mut Self, pair: Pair<'_, R>) -> Result<(), Box<dyn std::error::Error>> {
+
+        match rule_of(&pair) {
             generic::Rule::comment => {
                 info!("generic::Rule::comment: {:?}", pair.as_span().as_str());
             }
-            generic::Rule::EOI => { 
+            generic::Rule::EOI => {
                 info!("generic::Rule::EOI at {:?}", pair.line_col());
             }
-    
+
             generic::Rule::WHITESPACE => {}
             generic::Rule::acct_descriptor => { dump_pair(&pair); return Ok(()); }
             generic::Rule::acct_separator => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::balance_directive => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::amount => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::balance_directive => {
+                let bal = handle_balance_directive(&pair)?;
+                self.add_directive(RawDirective::Balance(bal));
+            }
+            generic::Rule::booking_method => { dump_pair(&pair); return Ok(()); }
             generic::Rule::comment_or_newline => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::comment_text => { dump_pair(&pair); return Ok(()); }
             generic::Rule::comment_token => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_component => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_components => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_label => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_per_unit => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_spec => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::cost_total => { dump_pair(&pair); return Ok(()); }
             generic::Rule::currency => { dump_pair(&pair); return Ok(()); }
             generic::Rule::decimal_value => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::directive_close => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::directive_commodity => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::directive_open => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::directives => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::directive_close => {
+                let close = handle_directive_close(&pair)?;
+                self.add_directive(RawDirective::Close(close));
+            }
+            generic::Rule::directive_commodity => {
+                let cmdty = handle_directive_commodity(&pair)?;
+                self.add_directive(RawDirective::Commodity(cmdty));
+            }
+            generic::Rule::directive_document => {
+                return self.handle_directive_document(&pair);
+            }
+            generic::Rule::directive_event => {
+                let event = handle_directive_event(&pair)?;
+                self.add_directive(RawDirective::Event(event));
+            }
+            generic::Rule::directive_include => {
+                return self.handle_directive_include(&pair);
+            }
+            generic::Rule::directive_note => {
+                let note = handle_directive_note(&pair)?;
+                self.add_directive(RawDirective::Note(note));
+            }
+            generic::Rule::directive_open => {
+                let open = handle_directive_open(&pair)?;
+                self.add_directive(RawDirective::Open(open));
+            }
+            generic::Rule::directive_option => {
+                return self.handle_directive_option(&pair);
+            }
+            generic::Rule::directive_pad => {
+                let pad = handle_directive_pad(&pair)?;
+                self.add_directive(RawDirective::Pad(pad));
+            }
+            generic::Rule::directive_plugin => {
+                let mut plugin = handle_directive_plugin(&pair)?;
+                self.stamp_source(|f| f(&mut plugin.pinfo));
+                self.pl.add_plugin(plugin);
+            }
+            generic::Rule::directive_poptag => {
+                return self.handle_directive_poptag(&pair);
+            }
+            generic::Rule::directive_price => {
+                let price = handle_directive_price(&pair)?;
+                self.add_directive(RawDirective::Price(price));
+            }
+            generic::Rule::directive_pushtag => {
+                return self.handle_directive_pushtag(&pair);
+            }
+            generic::Rule::directives => {
+                return self.handle_ledger_rule(&pair);
+            }
+            generic::Rule::document_path => { dump_pair(&pair); return Ok(()); }
             generic::Rule::empty_line => {}
+            generic::Rule::event_name => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::event_value => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::include_path => { dump_pair(&pair); return Ok(()); }
             generic::Rule::iso8601_date_extended => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::generic_ledger => { 
-                return handle_ledger_rule(&pair);
-            }
-            generic::Rule::options => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::posting_basic => { 
-                dump_pair(&pair); return Ok(());
+            generic::Rule::link => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::link_name => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_bool => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_end => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_key => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_line => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_number => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_string => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::metadata_value => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::open_currencies => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::generic_ledger => {
+                return self.handle_ledger_rule(&pair);
             }
+            generic::Rule::note_text => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::option_name => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::option_value => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::plugin_config => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::plugin_name => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::posting_basic => { dump_pair(&pair); return Ok(()); }
             generic::Rule::posting_indent => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::price_annotation => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::price_per_unit => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::price_total => { dump_pair(&pair); return Ok(()); }
             generic::Rule::sub_acct => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::tag => { dump_pair(&pair); return Ok(()); }
+            generic::Rule::tag_name => { dump_pair(&pair); return Ok(()); }
             generic::Rule::top_level_acct => { dump_pair(&pair); return Ok(()); }
             generic::Rule::trans_annotation => { dump_pair(&pair); return Ok(()); }
             generic::Rule::trans_description => { dump_pair(&pair); return Ok(()); }
             generic::Rule::trans_description_text => { dump_pair(&pair); return Ok(()); }
-            generic::Rule::trans_header => {
-                let mut xn = raw_transaction::RawTransaction::default();
-                return handle_trans_header(&mut xn, &pair);
-            }
+            generic::Rule::trans_header => { dump_pair(&pair); return Ok(()); }
             generic::Rule::transaction_block => {
                 let mut xn = raw_transaction::RawTransaction::default();
-                return handle_trans_block(&mut xn, &pair);
+                handle_trans_block(&mut xn, &pair)?;
+                xn.tags.extend(self.tag_stack.iter().map(|(tag, _)| tag.clone()));
+                self.stamp_source(|f| xn.for_each_pinfo_mut(f));
+                balancing::infer_elided_amounts(&mut xn)?;
+                self.pl.add_transaction(xn);
             }
         }
-    
+
         return Ok(());
-    
+
     }
-    
+
+
+    fn handle_ledger_rule<R: LedgerRule>(self: &mut Self, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+        for inner_pair in pair.clone().into_inner() {
+
+            match self.handle_pair(inner_pair) {
+                Ok(_p) => { /* handle_pair does all the work */ }
+
+                Err(mut err) => {
+                    warn!("handle_pair failed in handle_ledger. err: {err}");
+                    // errors located by the free handlers lack the source being parsed
+                    if let Some(e) = err.downcast_mut::<LedgerError>().filter(|e| e.pinfo.source == SourceId::default()) {
+                        self.stamp_source(|f| f(&mut e.pinfo));
+                    }
+                    return Err(err);
+                }
+            };
+        }
+
+        return Ok(());
+    }
+
 }
 
 
-fn dump_rule_of_pair(p: &Pair<generic::Rule>) {
+fn dump_rule_of_pair<R: LedgerRule>(p: &Pair<R>) {
     info!("RULE: {:?} at {:?}; SPAN: {:?}", &p.as_rule(), &p.line_col(), &p.as_span());
 }
 
@@ -107,88 +441,410 @@ fn dump_rule(r:&generic::Rule, s:&Span) {
     info!("RULE: {:?}; SPAN: {:?}", &r, &s);
 }
 
-fn dump_pair(p:&Pair<generic::Rule>) {
+fn dump_pair<R: LedgerRule>(p:&Pair<R>) {
     dump_rule_of_pair(p);
 }
 
 
+fn file_position(pos: &Position) -> FilePosition {
+    let (line, col) = pos.line_col();
+    return FilePosition { line, col, offset: pos.pos() };
+}
 
-fn handle_ledger_rule(pair: & Pair<generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
-    for inner_pair in pair.clone().into_inner() {
+// The rules the ledger is built from: those of the generic grammar, or of a grammar, such as
+//  beancount's, whose every rule is a generic one (cf. pta_parser::parsers::beancount)
+trait LedgerRule: RuleType + Into<generic::Rule> {}
 
-        match handle_pair(inner_pair) {
-            Ok(_p) => { /* handle_pair does all the work */ }
+impl<R: RuleType + Into<generic::Rule>> LedgerRule for R {}
 
-            Err(err) => {
-                warn!("handle_pair failed in handle_ledger. err: {err}");
-                return Err(err);
-            }
-        };
+fn rule_of<R: LedgerRule>(pair: &Pair<R>) -> generic::Rule {
+    return pair.as_rule().into();
+}
+
+fn parser_info<R: RuleType>(pair: &Pair<R>) -> ParserInfo {
+    let span = pair.as_span();
+    return ParserInfo {
+        position: file_position(&span.start_pos()),
+        end: file_position(&span.end_pos()),
+        ..Default::default()
+    };
+}
+
+
+fn acct_descriptor<R: LedgerRule>(pair: &Pair<R>) -> raw_transaction::RawAccountDescriptor {
+    return raw_transaction::RawAccountDescriptor {
+        path: pair.as_str().to_string(),
+        pinfo: parser_info(pair),
+    };
+}
+
+
+fn handle_directive_open<R: LedgerRule>(pair: &Pair<R>) -> Result<RawOpen, Box<dyn std::error::Error>> {
+    info!("handling directive_open...");
+
+    let mut open = RawOpen { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { open.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor => { open.acct = acct_descriptor(&inner); }
+            generic::Rule::currency => { open.currencies.push(inner.as_str().to_string()); }
+            generic::Rule::booking_method => { open.booking = Some(inner.as_str().parse::<BookingMethod>()?); }
+            generic::Rule::comment_text => { open.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut open.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
     }
 
-    return Ok(());
+    return Ok(open);
+}
+
+fn handle_directive_close<R: LedgerRule>(pair: &Pair<R>) -> Result<RawClose, Box<dyn std::error::Error>> {
+    info!("handling directive_close...");
+
+    let mut close = RawClose { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { close.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor => { close.acct = acct_descriptor(&inner); }
+            generic::Rule::comment_text => { close.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut close.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(close);
 }
 
-#[allow(dead_code)]  // TODO: REMOVE allow dead code
-fn handle_posting_basic(_xn: &mut raw_transaction::RawTransaction, pair: &Pair<generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_directive_commodity<R: LedgerRule>(pair: &Pair<R>) -> Result<RawCommodity, Box<dyn std::error::Error>> {
+    info!("handling directive_commodity...");
+
+    let mut cmdty = RawCommodity { pinfo: parser_info(pair), ..Default::default() };
 
-    match generic::Parser::parse(generic::Rule::posting_basic, pair.as_span().as_str()) {
-        Ok(_posting) => {
-            info!("handling posting_basic");
-            // handle_posting_basic(xn, posting);  TODO: fix
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { cmdty.date = inner.as_str().to_string(); }
+            generic::Rule::currency => { cmdty.currency = inner.as_str().to_string(); }
+            generic::Rule::comment_text => { cmdty.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut cmdty.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
         }
-        
-        Err(e) => {
-            warn!("failed to parse with posting_basic. err: {e}");
-            return Err(Box::new(e));
+    }
+
+    return Ok(cmdty);
+}
+
+// The name of the tag or link within the pair, without its '#' or '^'
+fn name_of<R: LedgerRule>(pair: &Pair<R>) -> String {
+    return pair.clone().into_inner().flatten()
+        .find(|inner| matches!(rule_of(inner), generic::Rule::tag_name | generic::Rule::link_name))
+        .map(|inner| inner.as_str().to_string())
+        .unwrap_or_default();
+}
+
+fn handle_directive_note<R: LedgerRule>(pair: &Pair<R>) -> Result<RawNote, Box<dyn std::error::Error>> {
+    info!("handling directive_note...");
+
+    let mut note = RawNote { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { note.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor => { note.acct = acct_descriptor(&inner); }
+            generic::Rule::note_text => { note.text = inner.as_str().to_string(); }
+            generic::Rule::comment_text => { note.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut note.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
         }
-            
+    }
+
+    return Ok(note);
+}
+
+// The document's path is resolved by the builder, which knows the file being parsed
+fn handle_directive_document<R: LedgerRule>(pair: &Pair<R>) -> Result<RawDocument, Box<dyn std::error::Error>> {
+    info!("handling directive_document...");
+
+    let mut doc = RawDocument { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { doc.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor => { doc.acct = acct_descriptor(&inner); }
+            generic::Rule::document_path => { doc.path = inner.as_str().to_string(); }
+            generic::Rule::comment_text => { doc.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut doc.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(doc);
+}
+
+fn handle_directive_event<R: LedgerRule>(pair: &Pair<R>) -> Result<RawEvent, Box<dyn std::error::Error>> {
+    info!("handling directive_event...");
+
+    let mut event = RawEvent { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { event.date = inner.as_str().to_string(); }
+            generic::Rule::event_name => { event.name = inner.as_str().to_string(); }
+            generic::Rule::event_value => { event.value = inner.as_str().to_string(); }
+            generic::Rule::comment_text => { event.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut event.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(event);
+}
+
+fn handle_directive_plugin<R: LedgerRule>(pair: &Pair<R>) -> Result<RawPlugin, Box<dyn std::error::Error>> {
+    info!("handling directive_plugin...");
+
+    let mut plugin = RawPlugin { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::plugin_name => { plugin.name = inner.as_str().to_string(); }
+            generic::Rule::plugin_config => { plugin.config = Some(inner.as_str().to_string()); }
+            generic::Rule::comment_text => { plugin.comment = inner.as_str().trim().to_string(); }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(plugin);
+}
+
+fn handle_directive_pad<R: LedgerRule>(pair: &Pair<R>) -> Result<RawPad, Box<dyn std::error::Error>> {
+    info!("handling directive_pad...");
+
+    let mut pad = RawPad { pinfo: parser_info(pair), ..Default::default() };
+
+    // the padded account is named first, then the account it is padded from
+    let mut accts = 0;
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { pad.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor if accts == 0 => { pad.acct = acct_descriptor(&inner); accts += 1; }
+            generic::Rule::acct_descriptor => { pad.source_acct = acct_descriptor(&inner); }
+            generic::Rule::comment_text => { pad.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut pad.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(pad);
+}
+
+fn handle_directive_price<R: LedgerRule>(pair: &Pair<R>) -> Result<RawPrice, Box<dyn std::error::Error>> {
+    info!("handling directive_price...");
+
+    let mut price = RawPrice { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { price.date = inner.as_str().to_string(); }
+            generic::Rule::currency => { price.currency = inner.as_str().to_string(); }
+            generic::Rule::amount => { price.amount = handle_amount(&inner)?; }
+            generic::Rule::comment_text => { price.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut price.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(price);
+}
+
+fn handle_balance_directive<R: LedgerRule>(pair: &Pair<R>) -> Result<RawBalance, Box<dyn std::error::Error>> {
+    info!("handling balance_directive...");
+
+    let mut bal = RawBalance { pinfo: parser_info(pair), ..Default::default() };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { bal.date = inner.as_str().to_string(); }
+            generic::Rule::acct_descriptor => { bal.acct = acct_descriptor(&inner); }
+            generic::Rule::decimal_value => { bal.amount.number = inner.as_str().parse::<Decimal>()?; }
+            generic::Rule::currency => { bal.amount.commodity = inner.as_str().to_string(); }
+            generic::Rule::comment_text => { bal.comment = inner.as_str().trim().to_string(); }
+            generic::Rule::metadata_line => { add_metadata(&mut bal.meta, &inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(bal);
+}
+
+
+fn handle_amount<R: LedgerRule>(pair: &Pair<R>) -> Result<Amount, Box<dyn std::error::Error>> {
+    let mut amount = Amount::default();
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::decimal_value => { amount.number = inner.as_str().parse::<Decimal>()?; }
+            generic::Rule::currency => { amount.commodity = inner.as_str().to_string(); }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(amount);
+}
+
+fn handle_cost_spec<R: LedgerRule>(pair: &Pair<R>) -> Result<Cost, Box<dyn std::error::Error>> {
+    info!("handling cost spec...");
+
+    let mut cost = Cost {
+        kind: if rule_of(pair) == generic::Rule::cost_total { CostKind::Total } else { CostKind::PerUnit },
+        pinfo: parser_info(pair),
+        ..Default::default()
+    };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::amount => { cost.amount = Some(handle_amount(&inner)?); }
+            generic::Rule::iso8601_date_extended => { cost.date = Some(inner.as_str().to_string()); }
+            generic::Rule::cost_label => { cost.label = Some(inner.as_str().to_string()); }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(cost);
+}
+
+fn handle_price_annotation<R: LedgerRule>(pair: &Pair<R>) -> Result<Price, Box<dyn std::error::Error>> {
+    info!("handling price annotation...");
+
+    let mut price = Price {
+        kind: if rule_of(pair) == generic::Rule::price_total { PriceKind::Total } else { PriceKind::PerUnit },
+        pinfo: parser_info(pair),
+        ..Default::default()
+    };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::amount => { price.amount = handle_amount(&inner)?; }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    return Ok(price);
+}
+
+
+// Adds the key and typed value of a metadata line; each key may be given once per item
+fn add_metadata<R: LedgerRule>(meta: &mut Metadata, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut key = "";
+    let mut value = None;
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::metadata_key => { key = inner.as_str(); }
+            generic::Rule::metadata_string => { value = Some(MetaValue::String(inner.as_str().to_string())); }
+            generic::Rule::iso8601_date_extended => { value = Some(MetaValue::Date(inner.as_str().to_string())); }
+            generic::Rule::metadata_number => { value = Some(MetaValue::Number(inner.as_str().parse::<Decimal>()?)); }
+            generic::Rule::metadata_bool => { value = Some(MetaValue::Bool(inner.as_str() == "TRUE")); }
+            generic::Rule::currency => { value = Some(MetaValue::Currency(inner.as_str().to_string())); }
+            generic::Rule::acct_descriptor => { value = Some(MetaValue::Account(inner.as_str().to_string())); }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    let value = value.ok_or_else(|| format!("metadata {key} has no value"))?;
+    if meta.insert(key.to_string(), value).is_some() {
+        return Err(Box::new(LedgerError::new(LedgerErrorKind::DuplicateMetadata,
+            format!("duplicate metadata key {key}"), &parser_info(pair))));
     }
 
     return Ok(());
 }
 
-fn handle_trans_header(_: &mut raw_transaction::RawTransaction, _: &Pair<generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_posting_basic<R: LedgerRule>(xn: &mut raw_transaction::RawTransaction, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
+    info!("handling posting_basic...");
+
+    let mut posting = raw_transaction::RawPosting {
+        pinfo: parser_info(pair),
+        ..Default::default()
+    };
+
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::acct_descriptor => { posting.acct = acct_descriptor(&inner); }
+            generic::Rule::decimal_value => {
+                posting.amount.get_or_insert_with(Amount::default).number = inner.as_str().parse::<Decimal>()?;
+            }
+            generic::Rule::currency => {
+                posting.amount.get_or_insert_with(Amount::default).commodity = inner.as_str().to_string();
+            }
+            generic::Rule::cost_per_unit | generic::Rule::cost_total => {
+                posting.cost = Some(handle_cost_spec(&inner)?);
+            }
+            generic::Rule::price_per_unit | generic::Rule::price_total => {
+                posting.price = Some(handle_price_annotation(&inner)?);
+            }
+            generic::Rule::comment_text => {
+                posting.comment = inner.as_str().trim().to_string();
+            }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
+    xn.postings.push(posting);
+
+    return Ok(());
+}
+
+fn handle_trans_header<R: LedgerRule>(xn: &mut raw_transaction::RawTransaction, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
     info!("handling trans_header...");
 
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::iso8601_date_extended => { xn.date = inner.as_str().to_string(); }
+            generic::Rule::trans_annotation => { xn.anno = inner.as_str().to_string(); }
+            generic::Rule::trans_description_text => { xn.desc = inner.as_str().to_string(); }
+            generic::Rule::tag => { xn.tags.insert(name_of(&inner)); }
+            generic::Rule::link => { xn.links.insert(name_of(&inner)); }
+            generic::Rule::comment_text => { xn.comment = inner.as_str().trim().to_string(); }
+            _ => { dump_pair(&inner); }
+        }
+    }
+
     return Ok(());
 }
 
-fn handle_trans_block(xn: &mut raw_transaction::RawTransaction, pair: &Pair<generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+fn handle_trans_block<R: LedgerRule>(xn: &mut raw_transaction::RawTransaction, pair: &Pair<R>) -> Result<(), Box<dyn std::error::Error>> {
     info!("handling trans_block...");
 
-    xn.pinfo = ParserInfo {
-        position: FilePosition {
-            line: pair.line_col().0,
-            col: pair.line_col().1
-        }
-    };
+    xn.pinfo = parser_info(pair);
 
-    info!("parse with trans_header");
-    match generic::Parser::parse(generic::Rule::trans_header, &pair.as_span().as_str()) {
-        Ok(hdr) => {
-            for pair in hdr.into_iter() {
-                info!("attempt handle_trans_header on {}", pair.as_span().as_str());
-                match handle_trans_header(xn, &pair) {
-                    Ok(()) => {
-                        // TODO: REVIEW: should anything happen here?
-                    }
+    for inner in pair.clone().into_inner() {
+        match rule_of(&inner) {
+            generic::Rule::trans_header => {
+                if let Err(e) = handle_trans_header(xn, &inner) {
+                    warn!("handle_trans_header failed. err: {e}");
+                    return Err(e);
+                }
+            }
 
-                    Err(e) => {
-                        warn!("handle_trans_header failed. err: {e}");
-                        return Err(e);
-                    }
+            generic::Rule::posting_basic => {
+                if let Err(e) = handle_posting_basic(xn, &inner) {
+                    warn!("handle_posting_basic failed. err: {e}");
+                    return Err(e);
                 }
+            }
 
+            // metadata describes the posting it follows, if any, else the transaction
+            generic::Rule::metadata_line => {
+                let meta = match xn.postings.last_mut() {
+                    Some(posting) => &mut posting.meta,
+                    None => &mut xn.meta,
+                };
+                add_metadata(meta, &inner)?;
             }
-            // for p in &pair.into_inner() {
-            //     handle_posting_basic(&mut xn, &p);
-            // }
-        }
 
-        Err(e) => {
-            warn!("failed to parse with trans_header. err: {e}");
-            return Err(Box::new(e));
+            _ => { dump_pair(&inner); }
         }
     }
 
@@ -198,53 +854,435 @@ fn handle_trans_block(xn: &mut raw_transaction::RawTransaction, pair: &Pair<gene
 
 
 
+#[cfg(test)]
+mod ledger_builder_tests {
+    use super::*;
+    use rstest::rstest;
 
-fn handle_pair(pair: Pair<'_, generic::Rule>) -> Result<(), Box<dyn std::error::Error>> {
-    match pair.as_rule() {
-        generic::Rule::comment => {
-            info!("generic::Rule::comment: {:?}", pair.as_span().as_str());
-        }
-        generic::Rule::EOI => { 
-            info!("generic::Rule::EOI at {:?}", pair.line_col());
-        }
+    #[rstest]
+    #[case ("2009-01-09 ! \"Bitcoin launch date\" ;comment \n\tAssets    1.0000 ;posting comment\n\tEquity    -1.0000 \n", "")]
+    #[case ("2009-01-09 ! \"Bitcoin launch date\" ;comment \n\tAssets    1.0000 BTC ;posting comment\n\tEquity    -1.0000 BTC\n", "BTC")]
+    fn can_handle_trans_block(#[case] tblock: &str, #[case] cmdty: &str) {
+        let pair = generic::Parser::parse(generic::Rule::transaction_block, tblock)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .next().unwrap();
+
+        let mut xn = raw_transaction::RawTransaction::default();
+        handle_trans_block(&mut xn, &pair).unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(xn.date, "2009-01-09");
+        assert_eq!(xn.anno, "!");
+        assert_eq!(xn.desc, "Bitcoin launch date");
+        assert_eq!(xn.comment, "comment");
+        assert_eq!(xn.pinfo.position.line, 1);
+
+        assert_eq!(xn.postings.len(), 2);
+        assert_eq!(xn.postings[0].acct.path, "Assets");
+        assert_eq!(xn.postings[0].amount.as_ref().unwrap().number.to_string(), "1.0000");
+        assert_eq!(xn.postings[0].comment, "posting comment");
+        assert_eq!(xn.postings[0].pinfo.position.line, 2);
+        assert_eq!(xn.postings[1].acct.path, "Equity");
+        assert_eq!(xn.postings[1].amount.as_ref().unwrap().number.to_string(), "-1.0000");
+        assert!(xn.postings.iter().all(|p| p.amount.as_ref().unwrap().commodity == cmdty));
+        assert_eq!(xn.postings[0].commodity(), if cmdty.is_empty() { None } else { Some(cmdty) });
+        assert_eq!(xn.postings[1].pinfo.position.line, 3);
+    }
+
+    #[test]
+    fn can_handle_trans_tags_and_links() {
+        let tblock = "2009-01-09 ! \"Bitcoin launch date\" #genesis ^block-0 #btc/2009 ;comment\n\tAssets    1.0000\n\tEquity\n";
+        let pair = generic::Parser::parse(generic::Rule::transaction_block, tblock)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .next().unwrap();
 
-        generic::Rule::WHITESPACE => {}
-        generic::Rule::acct_descriptor => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::acct_separator => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::balance_directive => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::comment_or_newline => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::comment_token => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::currency => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::decimal_value => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::directive_close => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::directive_commodity => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::directive_open => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::directives => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::empty_line => {}
-        generic::Rule::iso8601_date_extended => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::generic_ledger => { 
-            return handle_ledger_rule(&pair);
+        let mut xn = raw_transaction::RawTransaction::default();
+        handle_trans_block(&mut xn, &pair).unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(xn.desc, "Bitcoin launch date");
+        assert_eq!(xn.tags.iter().collect::<Vec<_>>(), vec!["btc/2009", "genesis"]);
+        assert_eq!(xn.links.iter().collect::<Vec<_>>(), vec!["block-0"]);
+        assert_eq!(xn.comment, "comment");
+    }
+
+    #[rstest]
+    #[case ("  Assets:Coinbase    1.03683606 BTC { 381.9697397 USD, 2016-01-28 }\n", CostKind::PerUnit, Some("381.9697397 USD"), Some("2016-01-28"), None)]
+    #[case ("  Assets:Coinbase    1.03683606 BTC {{396.04 USD}} ; comment\n", CostKind::Total, Some("396.04 USD"), None, None)]
+    #[case ("  Assets:Coinbase    1.03683606 BTC {2016-01-28, \"lot 1\"}\n", CostKind::PerUnit, None, Some("2016-01-28"), Some("lot 1"))]
+    #[case ("  Assets:Coinbase    -1.03683606 BTC {}\n", CostKind::PerUnit, None, None, None)]
+    fn can_handle_posting_cost(#[case] posting: &str, #[case] kind: CostKind, #[case] amount: Option<&str>, #[case] date: Option<&str>, #[case] label: Option<&str>) {
+        let pair = generic::Parser::parse(generic::Rule::posting_basic, posting)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .next().unwrap();
+
+        let mut xn = raw_transaction::RawTransaction::default();
+        handle_posting_basic(&mut xn, &pair).unwrap_or_else(|e| panic!("{}", e));
+
+        let cost = xn.postings[0].cost.as_ref().expect("posting should have a cost");
+        assert_eq!(cost.kind, kind);
+        assert_eq!(cost.amount.as_ref().map(|a| a.to_string()).as_deref(), amount);
+        assert_eq!(cost.date.as_deref(), date);
+        assert_eq!(cost.label.as_deref(), label);
+    }
+
+    #[rstest]
+    #[case ("  Assets:EUR    -100.00 EUR @ 1.08 USD\n", PriceKind::PerUnit, "1.08 USD", "-108.0000 USD")]
+    #[case ("  Assets:EUR    -100.00 EUR @@ 108.00 USD ; comment\n", PriceKind::Total, "108.00 USD", "-108.00 USD")]
+    #[case ("  Assets:BTC    1.00 BTC {381.97 USD} @ 400.00 USD\n", PriceKind::PerUnit, "400.00 USD", "400.0000 USD")]
+    fn can_handle_posting_price(#[case] posting: &str, #[case] kind: PriceKind, #[case] amount: &str, #[case] converted: &str) {
+        let pair = generic::Parser::parse(generic::Rule::posting_basic, posting)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .next().unwrap();
+
+        let mut xn = raw_transaction::RawTransaction::default();
+        handle_posting_basic(&mut xn, &pair).unwrap_or_else(|e| panic!("{}", e));
+
+        let p = &xn.postings[0];
+        let price = p.price.as_ref().expect("posting should have a price");
+        assert_eq!(price.kind, kind);
+        assert_eq!(price.amount.to_string(), amount);
+        assert_eq!(price.converted(&p.amount.as_ref().unwrap().number).unwrap().to_string(), converted);
+    }
+
+    #[rstest]
+    #[case (generic::Rule::directive_open, "2001-09-11 open Assets:Cash USD, BTC \"FIFO\"\t;comment\n")]
+    #[case (generic::Rule::directive_close, "2001-09-12 close Assets:Cash\t;comment\n")]
+    #[case (generic::Rule::directive_commodity, "2001-09-11 commodity USD\t;comment\n")]
+    #[case (generic::Rule::balance_directive, "2001-09-11 balance Assets:Cash -0.456 USD\t;comment\n")]
+    #[case (generic::Rule::directive_price, "2001-09-11 price BTC 400.00 USD\t;comment\n")]
+    #[case (generic::Rule::directive_pad, "2001-09-11 pad Assets:Cash Equity:Opening\t;comment\n")]
+    #[case (generic::Rule::directive_note, "2001-09-11 note Assets:Cash \"Called the bank\"\t;comment\n")]
+    #[case (generic::Rule::directive_document, "2001-09-11 document Assets:Cash \"statements/2001-09.pdf\"\t;comment\n")]
+    #[case (generic::Rule::directive_event, "2001-09-11 event \"location\" \"New York, USA\"\t;comment\n")]
+    #[case (generic::Rule::directive_plugin, "plugin \"beancount.plugins.check_commodity\" \"strict\"\t;comment\n")]
+    fn can_handle_directive(#[case] r: generic::Rule, #[case] directive: &str) {
+        let pair = generic::Parser::parse(r, directive)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .next().unwrap();
+
+        match r {
+            generic::Rule::directive_open => {
+                let open = handle_directive_open(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(open.date, "2001-09-11");
+                assert_eq!(open.acct.path, "Assets:Cash");
+                assert_eq!(open.currencies, vec!["USD", "BTC"]);
+                assert_eq!(open.booking, Some(BookingMethod::Fifo));
+                assert_eq!(open.comment, "comment");
+            }
+            generic::Rule::directive_close => {
+                let close = handle_directive_close(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(close.date, "2001-09-12");
+                assert_eq!(close.acct.path, "Assets:Cash");
+                assert_eq!(close.comment, "comment");
+            }
+            generic::Rule::directive_commodity => {
+                let cmdty = handle_directive_commodity(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(cmdty.date, "2001-09-11");
+                assert_eq!(cmdty.currency, "USD");
+                assert_eq!(cmdty.comment, "comment");
+            }
+            generic::Rule::balance_directive => {
+                let bal = handle_balance_directive(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(bal.date, "2001-09-11");
+                assert_eq!(bal.acct.path, "Assets:Cash");
+                assert_eq!(bal.amount.to_string(), "-0.456 USD");
+                assert_eq!(bal.comment, "comment");
+            }
+            generic::Rule::directive_pad => {
+                let pad = handle_directive_pad(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(pad.date, "2001-09-11");
+                assert_eq!(pad.acct.path, "Assets:Cash");
+                assert_eq!(pad.source_acct.path, "Equity:Opening");
+                assert_eq!(pad.comment, "comment");
+            }
+            generic::Rule::directive_note => {
+                let note = handle_directive_note(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(note.date, "2001-09-11");
+                assert_eq!(note.acct.path, "Assets:Cash");
+                assert_eq!(note.text, "Called the bank");
+                assert_eq!(note.comment, "comment");
+            }
+            generic::Rule::directive_document => {
+                let doc = handle_directive_document(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(doc.date, "2001-09-11");
+                assert_eq!(doc.acct.path, "Assets:Cash");
+                assert_eq!(doc.path, "statements/2001-09.pdf");
+                assert_eq!(doc.comment, "comment");
+            }
+            generic::Rule::directive_event => {
+                let event = handle_directive_event(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(event.date, "2001-09-11");
+                assert_eq!(event.name, "location");
+                assert_eq!(event.value, "New York, USA");
+                assert_eq!(event.comment, "comment");
+            }
+            generic::Rule::directive_plugin => {
+                let plugin = handle_directive_plugin(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(plugin.name, "beancount.plugins.check_commodity");
+                assert_eq!(plugin.config.as_deref(), Some("strict"));
+                assert_eq!(plugin.comment, "comment");
+            }
+            generic::Rule::directive_price => {
+                let price = handle_directive_price(&pair).unwrap_or_else(|e| panic!("{}", e));
+                assert_eq!(price.date, "2001-09-11");
+                assert_eq!(price.currency, "BTC");
+                assert_eq!(price.amount.to_string(), "400.00 USD");
+                assert_eq!(price.comment, "comment");
+            }
+            _ => { panic!("unexpected rule {:?}", r); }
         }
-        generic::Rule::options => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::posting_basic => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::posting_indent => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::sub_acct => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::top_level_acct => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::trans_annotation => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::trans_description => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::trans_description_text => { dump_pair(&pair); return Ok(()); }
-        generic::Rule::trans_header => {
-            let mut xn = raw_transaction::RawTransaction::default();
-            return handle_trans_header(&mut xn, &pair);
+    }
+
+    #[test]
+    fn can_build_ledger_from_basic_ledger_file() {
+        let ledger = std::fs::read_to_string("../testdata/basic-ledger")
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(&ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(pl.transactions().count(), 3);
+        assert_eq!(pl.directives().count(), 7);
+        assert_eq!(pl.accounts().collect::<Vec<_>>(),
+            vec!["Assets", "Assets:Cash", "Liabilities", "assets", "assets:subacct1", "equity"]);
+        assert_eq!(pl.commodities().collect::<Vec<_>>(), vec!["BTC", "USD"]);
+
+        let btc = pl.directives().find(|d| matches!(d, RawDirective::Commodity(c) if c.currency == "BTC")).unwrap();
+        assert_eq!(btc.meta().get("price"), Some(&MetaValue::String("USD:coinbase/BTC-USD".to_string())));
+    }
+
+    #[test]
+    fn can_build_ledger_from_path_with_includes() {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_path(Path::new("../testdata/include/main.beancount"))
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // accounts.beancount is included twice but loaded once
+        assert_eq!(pl.directives().count(), 3);
+        assert_eq!(pl.transactions().count(), 2);
+
+        let file_of = |pinfo: &ParserInfo| pinfo.file.as_ref()
+            .map(|f| f.strip_prefix(Path::new("../testdata/include").canonicalize().unwrap()).unwrap().to_path_buf());
+
+        assert!(pl.directives().all(|d| file_of(d.pinfo()) == Some(PathBuf::from("accounts.beancount"))));
+
+        let xns: Vec<&RawTransaction> = pl.transactions().collect();
+        assert_eq!(file_of(&xns[0].pinfo), Some(PathBuf::from("2001/01.beancount")));
+        assert_eq!(file_of(&xns[1].pinfo), Some(PathBuf::from("2001/02.beancount")));
+        assert_eq!(xns[1].pinfo.position.line, 3);
+        assert_eq!(file_of(&xns[1].postings[1].pinfo), Some(PathBuf::from("2001/02.beancount")));
+
+        // main, accounts and both months
+        assert_eq!(pl.source_count(), 4);
+        assert_ne!(xns[0].pinfo.source, xns[1].pinfo.source);
+        assert_eq!(pl.source_file(xns[1].pinfo.source), xns[1].pinfo.file.as_deref());
+    }
+
+    #[test]
+    fn can_resolve_document_paths() {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_path(Path::new("../testdata/include/documents.beancount"))
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let base = Path::new("../testdata/include").canonicalize().unwrap();
+        let docs: Vec<&RawDocument> = pl.documents_of("Assets:Cash").collect();
+        assert_eq!(docs.len(), 2);
+        assert_eq!(docs[0].path, "statements/2001-09.pdf");
+        assert_eq!(docs[0].resolved, base.join("statements/2001-09.pdf"));
+        assert_eq!(docs[1].resolved, PathBuf::from("/archive/2001-10.pdf"));
+
+        assert_eq!(pl.notes_of("Assets:Cash").map(|n| n.text.as_str()).collect::<Vec<_>>(), vec!["Called the bank"]);
+        assert_eq!(pl.events().map(|e| (e.name.as_str(), e.value.as_str())).collect::<Vec<_>>(), vec![("location", "New York, USA")]);
+        assert_eq!(pl.accounts().collect::<Vec<_>>(), vec!["Assets:Cash"]);
+    }
+
+    #[rstest]
+    #[case (None, true)]
+    #[case (Some(Dialect::Generic), true)]
+    #[case (Some(Dialect::Beancount), false)]
+    fn can_build_ledger_in_dialect(#[case] dialect: Option<Dialect>, #[case] accepted: bool) {
+        let ledger = "2001-09-11 open assets:cash\n2001-09-12 * \"deposit\"\n  assets:cash    1.00 USD\n  equity\n";
+
+        let mut bldr = dialect.map_or_else(LedgerBuilder::default, LedgerBuilder::with_dialect);
+        let result = bldr.from_string(ledger);
+        assert_eq!(result.is_ok(), accepted);
+
+        if let Err(err) = result {
+            assert!(err.to_string().contains("expected"), "unexpected error: {}", err);
         }
-        generic::Rule::transaction_block => {
-            let mut xn = raw_transaction::RawTransaction::default();
-            return handle_trans_block(&mut xn, &pair);
+    }
+
+    #[test]
+    fn can_build_beancount_ledger_from_its_own_parse() {
+        let ledger = "option \"title\" \"Family\"\n2001-09-11 open Assets:Cash USD \"FIFO\"\n  owner: \"joint\"\n\
+            2001-09-11 price BTC 400.00 USD\n2001-09-12 * \"deposit\" #cash ^slip-1\n  Assets:Cash    1.00 BTC {381.97 USD} @ 400.00 USD\n  Equity:Opening\n";
+
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Beancount);
+        let beancount = format!("{:?}", bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e)));
+
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Generic);
+        let generic = format!("{:?}", bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e)));
+        assert_eq!(beancount, generic);
+    }
+
+    #[test]
+    fn can_build_generic_ledger_with_options() {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string("option \"title\" \"Family\"\n2001-09-11 open assets:cash\n").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(pl.options().title, "Family");
+        assert_eq!(bldr.dialect(), Dialect::Generic);
+    }
+
+    #[rstest]
+    #[case ("../testdata/dialect/valid.beancount", None)]
+    #[case ("../testdata/dialect/invalid.beancount", Some("4:3"))]
+    fn can_detect_dialect_of_path(#[case] path: &str, #[case] error_at: Option<&str>) {
+        let mut bldr = LedgerBuilder::default();
+
+        match (bldr.from_path(Path::new(path)), error_at) {
+            (Ok(pl), None) => { assert_eq!(pl.transactions().count(), 1); }
+            (Err(err), Some(at)) => {
+                let msg = err.to_string();
+                assert!(msg.contains("invalid.beancount") && msg.contains(at), "unexpected error: {}", msg);
+            }
+            (result, _) => { panic!("unexpected result: {:?}", result.map(|pl| pl.len())); }
         }
+        assert_eq!(bldr.dialect(), Dialect::Beancount);
+
+        // the generic grammar accepts lower case account names
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Generic);
+        assert!(bldr.from_path(Path::new(path)).is_ok());
     }
 
-    return Ok(());
+    #[test]
+    fn can_locate_source_text() {
+        let ledger = "2001-09-11 open Assets:Cash\n\n2001-09-12 * \"deposit\"\n  Assets:Cash    1.00 USD\n  Equity\n";
 
-}
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        let open = pl.directives().next().unwrap().pinfo();
+        assert_eq!(open.source, SourceId(1));
+        assert!(open.file.is_none());
+        assert_eq!(open.text(ledger), Some("2001-09-11 open Assets:Cash\n"));
+        assert_eq!((open.end.line, open.end.col, open.end.offset), (2, 1, 28));
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.pinfo.position.offset, 29);
+        assert_eq!(xn.postings[0].pinfo.text(ledger), Some("  Assets:Cash    1.00 USD\n"));
+        assert_eq!(xn.postings[0].acct.pinfo.text(ledger), Some("Assets:Cash"));
 
+        // the inferred posting keeps the span of the elided one
+        assert_eq!(xn.postings[1].pinfo.text(ledger), Some("  Equity\n"));
+        assert!(matches!(pl.find_at(SourceId(1), 60), Some(LedgerItem::Transaction(_))));
+    }
+
+    #[rstest]
+    #[case ("include \"../testdata/include/none-*.beancount\"\n", LedgerErrorKind::IncludeNotFound, "matches no files")]
+    #[case ("include \"../testdata/include/cycle/a.beancount\"\n", LedgerErrorKind::IncludeCycle, "b.beancount -> ")]
+    fn verify_include_error(#[case] ledger: &str, #[case] kind: LedgerErrorKind, #[case] msg: &str) {
+        let mut bldr = LedgerBuilder::default();
+        let err = bldr.from_string(ledger).expect_err("include should fail");
 
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("unexpected error: {}", err));
+        assert_eq!(err.kind, kind);
+        assert!(err.message.contains(msg), "missing '{}' in '{}'", msg, err.message);
+    }
+
+    #[test]
+    fn can_handle_metadata() {
+        let ledger = "2001-09-11 open Assets:Cash\n  opened: 2001-09-10\n  limit: -500 ; overdraft\n  \
+                closing: Equity:Closing\n  currency: USD\n  active: TRUE\n\
+            2001-09-12 * \"deposit\"\n  invoice: \"2001-001\"\n  \
+                Assets:Cash    1.00 USD\n    memo: \"change\"\n  Equity\n";
+
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        let meta = pl.directives().next().unwrap().meta();
+        assert_eq!(meta.iter().map(|(k, v)| format!("{k}: {v}")).collect::<Vec<_>>(),
+            vec!["opened: 2001-09-10", "limit: -500", "closing: Equity:Closing", "currency: USD", "active: TRUE"]);
+        assert_eq!(meta.get("limit"), Some(&MetaValue::Number(Decimal::new(-500, 0))));
+        assert_eq!(meta.get("closing"), Some(&MetaValue::Account("Equity:Closing".to_string())));
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.meta.get("invoice"), Some(&MetaValue::String("2001-001".to_string())));
+        assert_eq!(xn.postings[0].meta.get("memo"), Some(&MetaValue::String("change".to_string())));
+        assert!(xn.postings[1].meta.is_empty());
+    }
+
+    #[test]
+    fn verify_metadata_error() {
+        let mut bldr = LedgerBuilder::default();
+        let err = bldr.from_string("2001-09-11 open Assets:Cash\n  name: \"Cash\"\n  name: \"Petty cash\"\n")
+            .expect_err("duplicate key should be rejected");
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("unexpected error: {}", err));
+        assert_eq!(err.kind, LedgerErrorKind::DuplicateMetadata);
+        assert_eq!(err.pinfo.position.line, 3);
+        assert_eq!(err.pinfo.source, SourceId(1));
+    }
+
+    #[test]
+    fn can_apply_pushed_tags() {
+        let ledger = "2001-09-11 * \"before\"\n  Expenses:Food    1.00 USD\n  Assets:Cash\n\
+            pushtag #trip\n\
+            2001-09-12 * \"during\" #reimbursable ^invoice-1\n  Expenses:Food    1.00 USD\n  Assets:Cash\n\
+            pushtag #work\n\
+            poptag #trip\n\
+            2001-09-13 * \"after\" ^invoice-1\n  Assets:Cash    1.00 USD\n  Income:Reimbursed\n\
+            poptag #work\n";
+
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        let tags: Vec<Vec<&String>> = pl.transactions().map(|xn| xn.tags.iter().collect()).collect();
+        assert_eq!(tags, vec![vec![], vec!["reimbursable", "trip"], vec!["work"]]);
+
+        assert_eq!(pl.transactions_tagged("trip").map(|xn| xn.desc.as_str()).collect::<Vec<_>>(), vec!["during"]);
+        assert_eq!(pl.transactions_linked("invoice-1").count(), 2);
+    }
+
+    #[rstest]
+    #[case ("pushtag #trip\n\npushtag #work\npoptag #work\n", 1, "tag #trip is pushed but never popped")]
+    #[case ("pushtag #trip\npoptag #trip\npoptag #trip\n", 3, "tag #trip is popped but was not pushed")]
+    fn verify_tag_stack_error(#[case] ledger: &str, #[case] line: usize, #[case] msg: &str) {
+        let mut bldr = LedgerBuilder::default();
+        let err = bldr.from_string(ledger).expect_err("tag stack should be unbalanced");
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("unexpected error: {}", err));
+        assert_eq!(err.kind, LedgerErrorKind::UnbalancedTag);
+        assert_eq!(err.pinfo.position.line, line);
+        assert_eq!(err.message, msg);
+    }
+
+    #[test]
+    fn can_handle_options() {
+        let ledger = "option \"title\" \"Family Ledger\"\noption \"operating_currency\" \"USD\"\noption \"booking_method\" \"FIFO\"\n";
+
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(pl.options().title, "Family Ledger");
+        assert_eq!(pl.options().operating_currency, vec!["USD"]);
+        assert_eq!(pl.options().booking_method, BookingMethod::Fifo);
+        assert!(pl.is_empty());
+    }
+
+    #[test]
+    fn verify_option_error() {
+        let mut bldr = LedgerBuilder::default();
+        let err = bldr.from_string("\noption \"booking_method\" \"fifo\"\n").expect_err("option should be rejected");
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("unexpected error: {}", err));
+        assert_eq!(err.kind, LedgerErrorKind::InvalidOption);
+        assert_eq!(err.pinfo.position.line, 2);
+        assert!(err.message.contains("fifo"), "unexpected message '{}'", err.message);
+    }
+
+    #[test]
+    fn can_ignore_unknown_option() {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string("option \"render_commas\" \"TRUE\"\n2001-09-11 open Assets:Cash\n").unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(pl.directives().count(), 1);
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+// What hledger journals have of their own (cf. journal for what they share with ledger-cli's):
+//  - quantities are read with the decimal mark of the last decimal-mark directive, else as hledger
+//    infers it
+//  - name:value tags in notes add tags when valueless, else metadata; account type tags name the
+//    type in full
+//  - balance assertions on postings become PostingAssertions
+//  - multipliers (*AMOUNT) in automated postings multiply the matched amount
+
+
+use std::collections::BTreeSet;
+
+use pest::{Parser, iterators::{Pair, Pairs}};
+
+use pta_parser::parsers::hledger;
+use pta_types::*;
+
+use crate::ledger_error::*;
+
+use super::parser_info;
+use super::journal::{dump_pair, Journal, JournalDialect};
+
+
+#[derive(Default)]
+pub(super) struct Hledger {
+    // The decimal mark of the last decimal-mark directive; None to infer that of each quantity
+    decimal_mark: Option<char>,
+}
+
+impl JournalDialect for Hledger {
+    type Rule = hledger::Rule;
+
+    fn parse(ledger: &str) -> Result<Pairs<'_, hledger::Rule>, pest::error::Error<hledger::Rule>> {
+        return hledger::Parser::parse(hledger::Rule::hledger_journal, ledger);
+    }
+
+    // The quantity, its decimal mark being that of the decimal-mark directive, if any, else its
+    //  last comma or period unless that mark is repeated. As in hledger, a lone mark is thus a
+    //  decimal mark: 1,000 is 1. Any other marks must be the same and group digits in threes.
+    fn quantity(journal: &Journal<Self>, pair: &Pair<hledger::Rule>) -> Result<Decimal, LedgerError> {
+        let text = pair.as_str();
+        let invalid = |why: &str| LedgerError::new(LedgerErrorKind::InvalidQuantity,
+            format!("invalid quantity {text}: {why}"), &parser_info(pair));
+
+        let is_mark = |c: char| matches!(c, ',' | '.' | ' ');
+        let marks: Vec<char> = text.chars().filter(|c| is_mark(*c)).collect();
+        let decimal_mark = match (journal.dialect.decimal_mark, marks.last()) {
+            (Some(mark), _) => Some(mark),
+            (None, Some(&last)) if last != ' ' && marks.iter().filter(|m| **m == last).count() == 1 => Some(last),
+            _ => None,
+        };
+
+        let (integer, fraction) = match decimal_mark.and_then(|mark| text.rsplit_once(mark)) {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (text, None),
+        };
+
+        let group_marks: BTreeSet<char> = integer.chars().filter(|c| is_mark(*c)).collect();
+        if group_marks.len() > 1 || decimal_mark.is_some_and(|mark| group_marks.contains(&mark)) {
+            return Err(invalid("its digit group marks differ or include the decimal mark"));
+        }
+        if integer.split(is_mark).skip(1).any(|group| group.len() != 3) {
+            return Err(invalid("its digits must be grouped in threes"));
+        }
+        if fraction.is_some_and(|f| f.contains(is_mark)) {
+            return Err(invalid("digit group marks follow its decimal mark"));
+        }
+
+        let digits: String = integer.chars().filter(|c| !is_mark(*c)).collect();
+        let number = match fraction {
+            Some(fraction) => format!("{digits}.{fraction}"),
+            None => digits,
+        };
+        return number.parse::<Decimal>().map_err(|e| invalid(&e.to_string()));
+    }
+
+    // Tags are name:value anywhere in a note, the value ending at a comma
+    fn note(text: &str, meta: &mut Metadata, tags: &mut BTreeSet<String>) -> String {
+        let mut rest: Vec<&str> = Vec::new();
+
+        for segment in text.split(',') {
+            let tag = segment.split_once(':').filter(|(left, _)| !left.trim().is_empty() && !left.ends_with(char::is_whitespace));
+            let Some((left, value)) = tag else {
+                rest.push(segment.trim());
+                continue;
+            };
+
+            let (before, name) = left.trim_start().rsplit_once(char::is_whitespace).unwrap_or(("", left.trim_start()));
+            rest.push(before.trim());
+
+            match value.trim() {
+                "" => { tags.insert(name.to_string()); }
+                value => { meta.insert(name.to_string(), MetaValue::String(value.to_string())); }
+            }
+        }
+
+        return rest.into_iter().filter(|r| !r.is_empty()).collect::<Vec<&str>>().join(", ");
+    }
+
+    // The account's type tag names its type in full
+    fn account_directive(_journal: &mut Journal<Self>, open: &mut RawOpen, _pair: &Pair<hledger::Rule>) -> Result<(), LedgerError> {
+        if let Some(MetaValue::String(t)) = open.meta.get("type") {
+            let Some(full) = account_type(t) else {
+                return Err(LedgerError::new(LedgerErrorKind::InvalidAccountType,
+                    format!("account {} has type {t}; expected one of A, L, E, R, X, C or V", open.acct.path), &open.pinfo));
+            };
+            open.meta.insert("type".to_string(), MetaValue::String(full.to_string()));
+        }
+
+        return Ok(());
+    }
+
+    fn directive(journal: &mut Journal<Self>, pair: &Pair<hledger::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        match pair.as_rule() {
+            hledger::Rule::decimal_mark_directive => {
+                journal.dialect.decimal_mark = pair.clone().into_inner()
+                    .find(|inner| inner.as_rule() == hledger::Rule::decimal_mark)
+                    .and_then(|inner| inner.as_str().chars().next());
+            }
+            _ => { dump_pair(pair); }
+        }
+
+        return Ok(());
+    }
+
+    fn posting_part(journal: &Journal<Self>, p: &mut RawPosting, pair: &Pair<hledger::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        match pair.as_rule() {
+            // the factor of an automated posting
+            hledger::Rule::multiplier => {
+                if let Some(factor) = pair.clone().into_inner().next() {
+                    p.amount = Some(journal.amount(&factor)?);
+                }
+            }
+            hledger::Rule::balance_assertion => {
+                let op = pair.clone().into_inner()
+                    .find(|inner| inner.as_rule() == hledger::Rule::assertion_op)
+                    .map(|inner| inner.as_str())
+                    .unwrap_or_default();
+                p.assertion = Some(PostingAssertion {
+                    amount: journal.inner_amount(pair)?.unwrap_or_default(),
+                    sole: op.starts_with("=="),
+                    inclusive: op.ends_with('*'),
+                    pinfo: parser_info(pair),
+                });
+            }
+            _ => { dump_pair(pair); }
+        }
+
+        return Ok(());
+    }
+
+    // A factor of the matched amount is marked with '*'
+    fn factor(journal: &Journal<Self>, pair: &Pair<hledger::Rule>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        return Ok(pair.clone().into_inner()
+            .find(|inner| inner.as_rule() == hledger::Rule::multiplier)
+            .and_then(|multiplier| multiplier.into_inner().next())
+            .map(|amount| journal.amount(&amount)).transpose()?
+            .map(|amount| amount.number));
+    }
+}
+
+
+// The full name of one of hledger's account types, given by name or initial
+fn account_type(t: &str) -> Option<&'static str> {
+    match t.to_lowercase().as_str() {
+        "a" | "asset" | "assets" => { return Some("Asset"); }
+        "l" | "liability" | "liabilities" => { return Some("Liability"); }
+        "e" | "equity" => { return Some("Equity"); }
+        "r" | "revenue" | "revenues" => { return Some("Revenue"); }
+        "x" | "expense" | "expenses" => { return Some("Expense"); }
+        "c" | "cash" => { return Some("Cash"); }
+        "v" | "conversion" => { return Some("Conversion"); }
+        _ => { return None; }
+    }
+}
+
+
+
+#[cfg(test)]
+mod hledger_journal_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::balancing::{check_transactions, Tolerance};
+    use crate::ledger_builder::LedgerBuilder;
+    use pta_parser::parsers::Dialect;
+
+    fn build(journal: &str) -> ParsedLedger {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Hledger);
+        return std::mem::take(bldr.from_string(journal).unwrap_or_else(|e| panic!("{}", e)));
+    }
+
+    fn amounts(xn: &RawTransaction) -> Vec<String> {
+        return xn.postings.iter().map(|p| format!("{} {}", p.acct.path, p.amount.as_ref().unwrap())).collect();
+    }
+
+    #[test]
+    fn can_build_hledger_journal() {
+        let pl = build("Y 2023
+D $1,000.00
+P 2023/01/05 AAPL $150.00 ; closing
+P 1/6 AAPL 151.50
+
+1/5 * Grocery Store  ; food:, receipt: 1234, weekly shop
+    Expenses:Food  52.10
+    Assets:Checking  $-52.10 == $-52.10
+12/31 Brokerage
+    Assets:Brokerage  10 AAPL @ $150.00 =* 10 AAPL
+    Assets:Checking
+");
+
+        let prices: Vec<String> = pl.directives().filter_map(|d| match d {
+            RawDirective::Price(p) => Some(format!("{} {} {}", p.date, p.currency, p.amount)),
+            _ => None,
+        }).collect();
+        assert_eq!(prices, ["2023-01-05 AAPL 150.00 $", "2023-01-06 AAPL 151.50 $"]);
+
+        let xns: Vec<&RawTransaction> = pl.transactions().collect();
+        assert_eq!((xns[0].date.as_str(), xns[1].date.as_str()), ("2023-01-05", "2023-12-31"));
+        assert_eq!(amounts(xns[0]), ["Expenses:Food 52.10 $", "Assets:Checking -52.10 $"]);
+        assert_eq!(xns[0].tags.iter().map(String::as_str).collect::<Vec<&str>>(), ["food"]);
+        assert_eq!(xns[0].meta.get("receipt"), Some(&MetaValue::String("1234".to_string())));
+        assert_eq!(xns[0].comment, "weekly shop");
+
+        let assertion = xns[0].postings[1].assertion.as_ref().unwrap();
+        assert_eq!((assertion.amount.to_string().as_str(), assertion.sole, assertion.inclusive), ("-52.10 $", true, false));
+        let assertion = xns[1].postings[0].assertion.as_ref().unwrap();
+        assert_eq!((assertion.amount.to_string().as_str(), assertion.sole, assertion.inclusive), ("10 AAPL", false, true));
+
+        assert!(check_transactions(&pl, &Tolerance::default()).is_empty());
+    }
+
+    #[rstest]
+    #[case ("", "$1,000.50", "1000.50 $")]
+    #[case ("", "1.000,50 EUR", "1000.50 EUR")]
+    #[case ("", "1,5 EUR", "1.5 EUR")]                  // a lone mark is a decimal mark
+    #[case ("", "1,000 EUR", "1.000 EUR")]
+    #[case ("", "1.000.000 EUR", "1000000 EUR")]        // a repeated one groups digits
+    #[case ("", "-1 000 000.9455 EUR", "-1000000.9455 EUR")]
+    #[case ("decimal-mark .\n", "1,000.50 EUR", "1000.50 EUR")]
+    #[case ("decimal-mark .\n", "1,000 EUR", "1000 EUR")]
+    #[case ("decimal-mark ,\n", "1.000,50 EUR", "1000.50 EUR")]
+    #[case ("decimal-mark ,\n", "-3,5 EUR", "-3.5 EUR")]
+    fn can_apply_decimal_mark(#[case] directive: &str, #[case] amount: &str, #[case] expected: &str) {
+        let pl = build(&format!("{directive}2023/01/05 Payee\n    Assets:Cash  {amount}\n    Equity\n"));
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.postings[0].amount.as_ref().unwrap().to_string(), expected);
+    }
+
+    #[rstest]
+    #[case ("A", "Asset")]
+    #[case ("Liability", "Liability")]
+    #[case ("x", "Expense")]
+    #[case ("C", "Cash")]
+    fn can_handle_account_type(#[case] t: &str, #[case] expected: &str) {
+        let pl = build(&format!("account Assets:Checking  ; type: {t}, joint:\n"));
+
+        let RawDirective::Open(open) = pl.directives().next().unwrap() else { panic!("expected an open") };
+        assert_eq!(open.meta.get("type"), Some(&MetaValue::String(expected.to_string())));
+        assert_eq!(open.meta.get("joint"), Some(&MetaValue::Bool(true)));
+    }
+
+    #[test]
+    fn can_ignore_alias_sub_directive() {
+        // unlike ledger-cli, hledger has no alias sub-directive; it is kept as metadata only
+        let pl = build("account Assets:Checking\n    alias chk\n2023/01/05 Payee\n    chk  $10\n    Equity\n");
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.postings[0].acct.path, "chk");
+    }
+
+    #[test]
+    fn can_apply_hledger_multiplier() {
+        let pl = build("D $1.00
+= /^Expenses:Food/
+    (Budget:Food)  *-1
+    (Charity)  0.50
+2023/01/05 Grocery Store
+    Expenses:Food  $52.10
+    Assets:Checking
+");
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(amounts(xn)[2..], ["Budget:Food -52.10 $", "Charity 0.50 $"]);
+    }
+
+    #[test]
+    fn verify_multiplier_outside_automated_xact() {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Hledger);
+        let err = bldr.from_string("2023/01/05 Payee\n    Expenses:Food  *-1\n    Equity\n").unwrap_err();
+
+        // a multiplier has nothing to multiply in an ordinary transaction
+        assert!(err.to_string().contains("2:20"), "expected an error at the multiplier: {}", err);
+    }
+
+    #[rstest]
+    #[case ("account Assets:Checking  ; type: Savings\n", LedgerErrorKind::InvalidAccountType, 1)]
+    #[case ("\n1/5 Payee\n    Assets:Cash  $1.00\n    Equity\n", LedgerErrorKind::MissingYear, 2)]
+    #[case ("decimal-mark .\n2023/01/05 Payee\n    Assets:Cash  1.000.000 EUR\n    Equity\n", LedgerErrorKind::InvalidQuantity, 3)]
+    #[case ("decimal-mark .\n2023/01/05 Payee\n    Assets:Cash  1,5 EUR\n    Equity\n", LedgerErrorKind::InvalidQuantity, 3)]
+    #[case ("decimal-mark ,\n2023/01/05 Payee\n    Assets:Cash  1.000,000.5 EUR\n    Equity\n", LedgerErrorKind::InvalidQuantity, 3)]
+    #[case ("2023/01/05 Payee\n    Assets:Cash  1,000.000,50 EUR\n    Equity\n", LedgerErrorKind::InvalidQuantity, 2)]
+    #[case ("2023/01/05 Payee\n    Assets:Cash  1 000,000.50 EUR\n    Equity\n", LedgerErrorKind::InvalidQuantity, 2)]
+    fn verify_hledger_error(#[case] journal: &str, #[case] kind: LedgerErrorKind, #[case] line: usize) {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Hledger);
+        let err = bldr.from_string(journal).unwrap_err();
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("expected a LedgerError: {}", err));
+        assert_eq!(err.kind, kind);
+        assert_eq!(err.pinfo.position.line, line);
+    }
+}
@@ -0,0 +1,486 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+// Builds the journals of ledger-cli and hledger into the shared model. Their grammars differ too
+//  much from the generic one to share its handlers, but little from each other: the rules they
+//  share (cf. journal.pest) are handled here and those of a dialect's own by its JournalDialect
+//  (cf. ledger_cli and hledger_journal). Journals are mapped as follows:
+//  - dates become YYYY-MM-DD, taking the year of the last Y directive when they have none; the
+//    state (* or !) becomes the annotation, else "txn"
+//  - the payee becomes the description; the code and auxiliary date become metadata
+//  - notes add tags and metadata, as the dialect reads them, to what they annotate
+//  - account and commodity directives become undated open and commodity directives, with their
+//    sub-directives and valueless tags as metadata
+//  - amounts without a commodity take that of the last D directive, but for the factors of
+//    automated postings
+//  - aliases and applied accounts are expanded in account names
+//  - automated transactions add their postings to the later transactions they match
+//  - market prices become price directives
+//  - payee directives and periodic transactions (i.e., budgets) have no counterpart and are skipped
+
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::{info, warn};
+use pest::{RuleType, iterators::{Pair, Pairs}};
+use regex::Regex;
+
+use pta_parser::parsers::journal;
+use pta_types::*;
+
+use crate::balancing;
+use crate::ledger_error::*;
+
+use super::{parser_info, LedgerBuilder};
+
+
+// What the journals of a dialect have of their own: the grammar they are parsed with, how their
+//  quantities and notes are read and the rules which they do not share (cf. journal::Rule)
+pub(super) trait JournalDialect: Default {
+    type Rule: RuleType + Send + Sync + 'static + TryInto<journal::Rule, Error = Self::Rule>;
+
+    fn parse(ledger: &str) -> Result<Pairs<'_, Self::Rule>, pest::error::Error<Self::Rule>>;
+
+    fn quantity(journal: &Journal<Self>, pair: &Pair<Self::Rule>) -> Result<Decimal, LedgerError>;
+
+    // Adds the tags of the note to 'tags' and its metadata to 'meta', returning the rest of its text
+    fn note(text: &str, meta: &mut Metadata, tags: &mut BTreeSet<String>) -> String;
+
+    // Completes the open directive of the account directive
+    fn account_directive(journal: &mut Journal<Self>, open: &mut RawOpen, pair: &Pair<Self::Rule>) -> Result<(), LedgerError>;
+
+    fn directive(journal: &mut Journal<Self>, pair: &Pair<Self::Rule>) -> Result<(), Box<dyn std::error::Error>>;
+
+    fn posting_part(journal: &Journal<Self>, p: &mut RawPosting, pair: &Pair<Self::Rule>) -> Result<(), Box<dyn std::error::Error>>;
+
+    // The factor by which the automated posting multiplies the matched amount, if it does
+    fn factor(journal: &Journal<Self>, pair: &Pair<Self::Rule>) -> Result<Option<Decimal>, Box<dyn std::error::Error>>;
+}
+
+// The directives of a journal which apply to what follows them
+#[derive(Default)]
+pub(super) struct Journal<D: JournalDialect> {
+    // alias => account
+    pub(super) aliases: BTreeMap<String, String>,
+    // The accounts applied by apply account, outermost first
+    applied: Vec<String>,
+    automated: Vec<AutomatedXact>,
+    // The year of dates without one (Y) and the commodity of amounts without one (D)
+    year: Option<String>,
+    default_commodity: Option<String>,
+    pub(super) dialect: D,
+}
+
+// The postings an automated transaction adds for each posting whose account matches its predicate,
+//  each with the factor by which it multiplies the matched amount, if it does
+struct AutomatedXact {
+    predicate: Regex,
+    postings: Vec<(RawPosting, Option<Decimal>)>,
+}
+
+impl LedgerBuilder {
+    pub(super) fn parse_journal<D: JournalDialect>(self: &mut Self, ledger: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let root = match D::parse(ledger) {
+            Ok(root) => root,
+            Err(err) => {
+                warn!("failed to parse the journal. err: {err}");
+                return Err(self.with_file(err));
+            }
+        };
+
+        let mut journal = Journal::<D>::default();
+        for pair in root.flat_map(|journal| journal.into_inner()) {
+            if let Err(mut err) = self.handle_journal_pair(&mut journal, &pair) {
+                warn!("handle_journal_pair failed in parse_journal. err: {err}");
+                if let Some(e) = err.downcast_mut::<LedgerError>() {
+                    self.stamp_source(|f| f(&mut e.pinfo));
+                }
+                return Err(err);
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn handle_journal_pair<D: JournalDialect>(self: &mut Self, journal: &mut Journal<D>, pair: &Pair<D::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        match journal_rule(pair) {
+            Some(journal::Rule::directive) => {
+                for inner in pair.clone().into_inner() {
+                    self.handle_journal_directive(journal, &inner)?;
+                }
+            }
+            Some(journal::Rule::xact) => {
+                let mut xn = journal.xact(pair)?;
+                self.stamp_source(|f| xn.for_each_pinfo_mut(f));
+                balancing::infer_elided_amounts(&mut xn)?;
+                journal.apply_automated(&mut xn)?;
+                // an automated posting may itself be elided
+                balancing::infer_elided_amounts(&mut xn)?;
+                self.pl.add_transaction(xn);
+            }
+            Some(journal::Rule::auto_xact) => {
+                let auto = journal.automated_xact(pair)?;
+                journal.automated.push(auto);
+            }
+            Some(journal::Rule::periodic_xact) => {
+                info!("skipping periodic transaction at {:?}; budgets are not modelled", pair.line_col());
+            }
+            _ => { dump_pair(pair); }
+        }
+
+        return Ok(());
+    }
+
+    fn handle_journal_directive<D: JournalDialect>(self: &mut Self, journal: &mut Journal<D>, pair: &Pair<D::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        match journal_rule(pair) {
+            Some(journal::Rule::account_directive) => {
+                let open = journal.account_directive(pair)?;
+                self.add_directive(RawDirective::Open(open));
+            }
+            Some(journal::Rule::commodity_directive) => {
+                let mut cmdty = RawCommodity { pinfo: parser_info(pair), ..Default::default() };
+                let mut tags = BTreeSet::new();
+                for inner in pair.clone().into_inner() {
+                    match journal_rule(&inner) {
+                        Some(journal::Rule::commodity) => { cmdty.currency = commodity(&inner); }
+                        Some(journal::Rule::note_text) => { journal.add_note(inner.as_str(), &mut cmdty.comment, &mut cmdty.meta, &mut tags); }
+                        Some(journal::Rule::sub_directive) => { add_sub_directive(&mut cmdty.meta, &inner); }
+                        _ => { dump_pair(&inner); }
+                    }
+                }
+                cmdty.meta.extend(tags.into_iter().map(|t| (t, MetaValue::Bool(true))));
+                self.add_directive(RawDirective::Commodity(cmdty));
+            }
+            Some(journal::Rule::price_directive) => {
+                let mut price = RawPrice { pinfo: parser_info(pair), ..Default::default() };
+                for inner in pair.clone().into_inner() {
+                    match journal_rule(&inner) {
+                        Some(journal::Rule::date) => { price.date = journal.date(&inner)?; }
+                        Some(journal::Rule::commodity) => { price.currency = commodity(&inner); }
+                        Some(journal::Rule::amount) => { price.amount = journal.amount_or_default(&inner)?; }
+                        Some(journal::Rule::note_text) => { price.comment = inner.as_str().trim().to_string(); }
+                        _ => { dump_pair(&inner); }
+                    }
+                }
+                self.add_directive(RawDirective::Price(price));
+            }
+            Some(journal::Rule::default_commodity) => {
+                let amount = match pair.clone().into_inner().find(|inner| journal_rule(inner) == Some(journal::Rule::amount)) {
+                    Some(inner) => journal.amount(&inner)?,
+                    None => Amount::default(),
+                };
+                journal.default_commodity = Some(amount.commodity);
+            }
+            Some(journal::Rule::default_year) => {
+                journal.year = Some(text_of(pair, journal::Rule::year).to_string());
+            }
+            Some(journal::Rule::alias_directive) => {
+                let alias = text_of(pair, journal::Rule::alias_name);
+                let acct = text_of(pair, journal::Rule::account_name);
+                journal.aliases.insert(alias.trim().to_string(), acct.trim().to_string());
+            }
+            Some(journal::Rule::end_aliases) => { journal.aliases.clear(); }
+            Some(journal::Rule::apply_account) => {
+                journal.applied.push(text_of(pair, journal::Rule::account_name).trim().to_string());
+            }
+            Some(journal::Rule::end_apply_account) => {
+                if journal.applied.pop().is_none() {
+                    warn!("end apply account at {:?} without an apply account", pair.line_col());
+                }
+            }
+            Some(journal::Rule::payee_directive) => {
+                info!("skipping payee directive at {:?}; payees are not declared", pair.line_col());
+            }
+            Some(_) => { dump_pair(pair); }
+            None => { D::directive(journal, pair)?; }
+        }
+
+        return Ok(());
+    }
+}
+
+
+impl<D: JournalDialect> Journal<D> {
+    // The account named by the pair: its alias (for the whole name or its first component)
+    //  expanded, then under the applied accounts
+    fn account(self: &Self, pair: &Pair<D::Rule>) -> RawAccountDescriptor {
+        let name = pair.as_str().trim();
+
+        let expanded = match (self.aliases.get(name), name.split_once(':')) {
+            (Some(acct), _) => acct.clone(),
+            (None, Some((first, rest))) => match self.aliases.get(first) {
+                Some(acct) => format!("{acct}:{rest}"),
+                None => name.to_string(),
+            },
+            (None, None) => name.to_string(),
+        };
+
+        let path = self.applied.iter().map(String::as_str)
+            .chain([expanded.as_str()])
+            .collect::<Vec<&str>>()
+            .join(":");
+
+        return RawAccountDescriptor { path, pinfo: parser_info(pair) };
+    }
+
+    // YYYY/M/D, YYYY-MM-DD, YYYY.MM.DD or, given a default year, M/D as YYYY-MM-DD
+    fn date(self: &Self, pair: &Pair<D::Rule>) -> Result<String, LedgerError> {
+        let parts: Vec<&str> = pair.as_str().split(['/', '-', '.']).collect();
+
+        let (year, month, day) = match parts.as_slice() {
+            [year, month, day] => (*year, *month, *day),
+            [month, day] => match &self.year {
+                Some(year) => (year.as_str(), *month, *day),
+                None => {
+                    return Err(LedgerError::new(LedgerErrorKind::MissingYear,
+                        format!("date {} has no year and follows no Y directive", pair.as_str()), &parser_info(pair)));
+                }
+            },
+            _ => (pair.as_str(), "", ""),
+        };
+
+        return Ok(format!("{year}-{month:0>2}-{day:0>2}"));
+    }
+
+    // The date within the pair, if any
+    fn inner_date(self: &Self, pair: &Pair<D::Rule>) -> Result<Option<String>, LedgerError> {
+        return pair.clone().into_inner()
+            .find(|inner| journal_rule(inner) == Some(journal::Rule::date))
+            .map(|inner| self.date(&inner))
+            .transpose();
+    }
+
+    // The amount, its quantity read as the dialect reads it
+    pub(super) fn amount(self: &Self, pair: &Pair<D::Rule>) -> Result<Amount, Box<dyn std::error::Error>> {
+        let mut negative = false;
+        let mut amount = Amount::default();
+
+        for inner in pair.clone().into_inner() {
+            match journal_rule(&inner) {
+                Some(journal::Rule::negative) => { negative = true; }
+                Some(journal::Rule::quantity) => { amount.number = D::quantity(self, &inner)?; }
+                Some(journal::Rule::commodity) => { amount.commodity = commodity(&inner); }
+                _ => { dump_pair(&inner); }
+            }
+        }
+
+        if negative {
+            amount = amount.checked_neg().ok_or_else(|| LedgerError::new(LedgerErrorKind::Overflow,
+                format!("amount {} overflows", pair.as_str()), &parser_info(pair)))?;
+        }
+
+        return Ok(amount);
+    }
+
+    // The amount, in the default commodity if it has none
+    fn amount_or_default(self: &Self, pair: &Pair<D::Rule>) -> Result<Amount, Box<dyn std::error::Error>> {
+        let mut amount = self.amount(pair)?;
+        if amount.commodity.is_empty() {
+            amount.commodity = self.default_commodity.clone().unwrap_or_default();
+        }
+
+        return Ok(amount);
+    }
+
+    // The amount within the pair, if any, in the default commodity if it has none
+    pub(super) fn inner_amount(self: &Self, pair: &Pair<D::Rule>) -> Result<Option<Amount>, Box<dyn std::error::Error>> {
+        return pair.clone().into_inner()
+            .find(|inner| journal_rule(inner) == Some(journal::Rule::amount))
+            .map(|inner| self.amount_or_default(&inner))
+            .transpose();
+    }
+
+    // Adds the note's tags and metadata, as the dialect reads them, and its other text to the comment
+    fn add_note(self: &Self, text: &str, comment: &mut String, meta: &mut Metadata, tags: &mut BTreeSet<String>) {
+        let text = D::note(text, meta, tags);
+
+        if !text.is_empty() {
+            if !comment.is_empty() {
+                comment.push('\n');
+            }
+            comment.push_str(&text);
+        }
+    }
+
+    // An open directive for the account; valueless tags become TRUE metadata
+    fn account_directive(self: &mut Self, pair: &Pair<D::Rule>) -> Result<RawOpen, Box<dyn std::error::Error>> {
+        let mut open = RawOpen { pinfo: parser_info(pair), ..Default::default() };
+        let mut tags = BTreeSet::new();
+
+        for inner in pair.clone().into_inner() {
+            match journal_rule(&inner) {
+                Some(journal::Rule::account_name) => { open.acct = self.account(&inner); }
+                Some(journal::Rule::note_text) => { self.add_note(inner.as_str(), &mut open.comment, &mut open.meta, &mut tags); }
+                Some(journal::Rule::sub_directive) => { add_sub_directive(&mut open.meta, &inner); }
+                _ => { dump_pair(&inner); }
+            }
+        }
+        open.meta.extend(tags.into_iter().map(|t| (t, MetaValue::Bool(true))));
+
+        D::account_directive(self, &mut open, pair)?;
+        return Ok(open);
+    }
+
+    fn xact(self: &Self, pair: &Pair<D::Rule>) -> Result<RawTransaction, Box<dyn std::error::Error>> {
+        info!("handling xact...");
+
+        let mut xn = RawTransaction { anno: "txn".to_string(), pinfo: parser_info(pair), ..Default::default() };
+
+        for inner in pair.clone().into_inner() {
+            match journal_rule(&inner) {
+                Some(journal::Rule::xact_header) => {
+                    for field in inner.into_inner() {
+                        match journal_rule(&field) {
+                            Some(journal::Rule::date) => { xn.date = self.date(&field)?; }
+                            Some(journal::Rule::aux_date) => {
+                                if let Some(date) = self.inner_date(&field)? {
+                                    xn.meta.insert("aux-date".to_string(), MetaValue::Date(date));
+                                }
+                            }
+                            Some(journal::Rule::state) => { xn.anno = field.as_str().to_string(); }
+                            Some(journal::Rule::code) => {
+                                xn.meta.insert("code".to_string(), MetaValue::String(field.as_str().to_string()));
+                            }
+                            Some(journal::Rule::payee) => { xn.desc = field.as_str().trim().to_string(); }
+                            Some(journal::Rule::note_text) => {
+                                self.add_note(field.as_str(), &mut xn.comment, &mut xn.meta, &mut xn.tags);
+                            }
+                            _ => { dump_pair(&field); }
+                        }
+                    }
+                }
+                // a note line annotates the posting above it, if any, else the transaction
+                Some(journal::Rule::note_line) => {
+                    let text = text_of(&inner, journal::Rule::note_text);
+                    match xn.postings.last_mut() {
+                        Some(p) => { self.add_note(text, &mut p.comment, &mut p.meta, &mut xn.tags); }
+                        None => { self.add_note(text, &mut xn.comment, &mut xn.meta, &mut xn.tags); }
+                    }
+                }
+                Some(journal::Rule::posting) => {
+                    let p = self.posting(&inner, &mut xn.tags)?;
+                    xn.postings.push(p);
+                }
+                _ => { dump_pair(&inner); }
+            }
+        }
+
+        return Ok(xn);
+    }
+
+    // The posting; tags in its note are added to 'tags', those of its transaction
+    fn posting(self: &Self, pair: &Pair<D::Rule>, tags: &mut BTreeSet<String>) -> Result<RawPosting, Box<dyn std::error::Error>> {
+        let mut p = RawPosting { pinfo: parser_info(pair), ..Default::default() };
+
+        for inner in pair.clone().into_inner() {
+            match journal_rule(&inner) {
+                Some(journal::Rule::state) => {
+                    p.meta.insert("state".to_string(), MetaValue::String(inner.as_str().to_string()));
+                }
+                Some(journal::Rule::account_name) => { p.acct = self.account(&inner); }
+                Some(rule @ (journal::Rule::virtual_account | journal::Rule::balanced_virtual_account)) => {
+                    p.kind = if rule == journal::Rule::virtual_account { PostingKind::Virtual } else { PostingKind::BalancedVirtual };
+                    if let Some(name) = inner.clone().into_inner().next() {
+                        p.acct = self.account(&name);
+                    }
+                }
+                Some(journal::Rule::amount) => { p.amount = Some(self.amount_or_default(&inner)?); }
+                Some(rule @ (journal::Rule::lot_per_unit | journal::Rule::lot_total)) => {
+                    let cost = p.cost.get_or_insert_with(Cost::default);
+                    cost.kind = if rule == journal::Rule::lot_total { CostKind::Total } else { CostKind::PerUnit };
+                    cost.amount = self.inner_amount(&inner)?;
+                    cost.pinfo = parser_info(&inner);
+                }
+                Some(journal::Rule::lot_date) => {
+                    let cost = p.cost.get_or_insert_with(|| Cost { pinfo: parser_info(&inner), ..Default::default() });
+                    cost.date = self.inner_date(&inner)?;
+                }
+                Some(rule @ (journal::Rule::price_per_unit | journal::Rule::price_total)) => {
+                    let kind = if rule == journal::Rule::price_total { PriceKind::Total } else { PriceKind::PerUnit };
+                    let amount = self.inner_amount(&inner)?.unwrap_or_default();
+                    p.price = Some(Price { kind, amount, pinfo: parser_info(&inner) });
+                }
+                Some(journal::Rule::note_text) => { self.add_note(inner.as_str(), &mut p.comment, &mut p.meta, tags); }
+                Some(_) => { dump_pair(&inner); }
+                None => { D::posting_part(self, &mut p, &inner)?; }
+            }
+        }
+
+        return Ok(p);
+    }
+
+    // The predicate is a regular expression, optionally between slashes, matched against account
+    //  names ignoring case
+    fn automated_xact(self: &Self, pair: &Pair<D::Rule>) -> Result<AutomatedXact, Box<dyn std::error::Error>> {
+        info!("handling auto_xact...");
+
+        let text = text_of(pair, journal::Rule::auto_predicate).trim();
+        let pattern = text.strip_prefix('/').and_then(|t| t.strip_suffix('/')).unwrap_or(text);
+        let predicate = Regex::new(&format!("(?i){pattern}")).map_err(|e| LedgerError::new(LedgerErrorKind::InvalidPredicate,
+            format!("invalid predicate {text} of automated transaction: {e}"), &parser_info(pair)))?;
+
+        let mut postings = Vec::new();
+        for inner in pair.clone().into_inner().filter(|inner| journal_rule(inner) == Some(journal::Rule::auto_posting)) {
+            let p = self.posting(&inner, &mut BTreeSet::new())?;
+            let factor = D::factor(self, &inner)?;
+            postings.push((p, factor));
+        }
+
+        return Ok(AutomatedXact { predicate, postings });
+    }
+
+    // Adds the postings of each automated transaction for each of the transaction's postings which
+    //  it matches
+    fn apply_automated(self: &Self, xn: &mut RawTransaction) -> Result<(), LedgerError> {
+        let mut added = Vec::new();
+
+        for auto in &self.automated {
+            for matched in xn.postings.iter().filter(|p| auto.predicate.is_match(&p.acct.path)) {
+                for (template, factor) in &auto.postings {
+                    let mut p = template.clone();
+                    if let (Some(factor), Some(amount)) = (factor, &matched.amount) {
+                        p.amount = Some(amount.checked_mul(factor).ok_or_else(|| LedgerError::new(LedgerErrorKind::Overflow,
+                            format!("automated posting to {} overflows", p.acct.path), &template.pinfo))?);
+                    }
+                    added.push(p);
+                }
+            }
+        }
+
+        xn.postings.extend(added);
+        return Ok(());
+    }
+}
+
+
+// The shared rule of the pair, or None for a rule of the dialect's own
+fn journal_rule<R: RuleType + TryInto<journal::Rule>>(pair: &Pair<R>) -> Option<journal::Rule> {
+    return pair.as_rule().try_into().ok();
+}
+
+pub(super) fn dump_pair<R: RuleType>(p: &Pair<R>) {
+    info!("RULE: {:?} at {:?}; SPAN: {:?}", &p.as_rule(), &p.line_col(), &p.as_span());
+}
+
+// The text of the first pair of the rule within the pair, or "" if there is none
+pub(super) fn text_of<'a, R: RuleType + TryInto<journal::Rule>>(pair: &Pair<'a, R>, rule: journal::Rule) -> &'a str {
+    return pair.clone().into_inner().flatten()
+        .find(|inner| journal_rule(inner) == Some(rule))
+        .map(|inner| inner.as_str())
+        .unwrap_or_default();
+}
+
+fn commodity<R: RuleType>(pair: &Pair<R>) -> String {
+    return pair.as_str().trim_matches('"').to_string();
+}
+
+// Adds the sub-directive as metadata, TRUE if it has no value
+fn add_sub_directive<R: RuleType + TryInto<journal::Rule>>(meta: &mut Metadata, pair: &Pair<R>) {
+    let name = text_of(pair, journal::Rule::sub_directive_name);
+    let value = text_of(pair, journal::Rule::sub_directive_value).trim();
+
+    let meta_value = if value.is_empty() { MetaValue::Bool(true) } else { MetaValue::String(value.to_string()) };
+    meta.insert(name.to_string(), meta_value);
+}
@@ -0,0 +1,262 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+// What ledger-cli journals have of their own (cf. journal for what they share with hledger's):
+//  - quantities group their integer digits with commas
+//  - ; :tag1:tag2: notes add tags, ; Key: value notes add metadata
+//  - alias sub-directives of an account directive name the account
+//  - amounts without a commodity in automated postings multiply the matched amount
+
+
+use std::collections::BTreeSet;
+
+use pest::{Parser, iterators::{Pair, Pairs}};
+
+use pta_parser::parsers::{journal, ledger};
+use pta_types::*;
+
+use crate::ledger_error::*;
+
+use super::parser_info;
+use super::journal::{dump_pair, text_of, Journal, JournalDialect};
+
+
+#[derive(Default)]
+pub(super) struct LedgerCli;
+
+impl JournalDialect for LedgerCli {
+    type Rule = ledger::Rule;
+
+    fn parse(ledger: &str) -> Result<Pairs<'_, ledger::Rule>, pest::error::Error<ledger::Rule>> {
+        return ledger::Parser::parse(ledger::Rule::ledger_journal, ledger);
+    }
+
+    fn quantity(_journal: &Journal<Self>, pair: &Pair<ledger::Rule>) -> Result<Decimal, LedgerError> {
+        return pair.as_str().replace(',', "").parse::<Decimal>().map_err(|e| LedgerError::new(LedgerErrorKind::InvalidQuantity,
+            format!("invalid quantity {}: {e}", pair.as_str()), &parser_info(pair)));
+    }
+
+    // A note of :tag1:tag2: adds tags and one of Key: value adds metadata
+    fn note(text: &str, meta: &mut Metadata, tags: &mut BTreeSet<String>) -> String {
+        let text = text.trim();
+
+        if text.len() > 1 && text.starts_with(':') && text.ends_with(':') && !text.contains(char::is_whitespace) {
+            tags.extend(text.split(':').filter(|t| !t.is_empty()).map(str::to_string));
+            return String::new();
+        }
+
+        if let Some((key, value)) = text.split_once(": ").filter(|(k, _)| !k.is_empty() && !k.contains(char::is_whitespace)) {
+            meta.insert(key.to_string(), MetaValue::String(value.trim().to_string()));
+            return String::new();
+        }
+
+        return text.to_string();
+    }
+
+    // Each alias sub-directive is an alias of the account
+    fn account_directive(journal: &mut Journal<Self>, open: &mut RawOpen, pair: &Pair<ledger::Rule>) -> Result<(), LedgerError> {
+        for sub in pair.clone().into_inner().filter(|inner| inner.as_rule() == ledger::Rule::sub_directive) {
+            if text_of(&sub, journal::Rule::sub_directive_name) == "alias" {
+                let alias = text_of(&sub, journal::Rule::sub_directive_value).trim();
+                journal.aliases.insert(alias.to_string(), open.acct.path.clone());
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn directive(_journal: &mut Journal<Self>, pair: &Pair<ledger::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        dump_pair(pair);
+        return Ok(());
+    }
+
+    fn posting_part(_journal: &Journal<Self>, _p: &mut RawPosting, pair: &Pair<ledger::Rule>) -> Result<(), Box<dyn std::error::Error>> {
+        dump_pair(pair);
+        return Ok(());
+    }
+
+    // An amount without a commodity is a factor of the matched amount
+    fn factor(journal: &Journal<Self>, pair: &Pair<ledger::Rule>) -> Result<Option<Decimal>, Box<dyn std::error::Error>> {
+        return Ok(pair.clone().into_inner()
+            .find(|inner| inner.as_rule() == ledger::Rule::amount)
+            .filter(|amount| amount.clone().into_inner().all(|inner| inner.as_rule() != ledger::Rule::commodity))
+            .map(|amount| journal.amount(&amount)).transpose()?
+            .map(|amount| amount.number));
+    }
+}
+
+
+
+#[cfg(test)]
+mod ledger_cli_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use pta_parser::parsers::Dialect;
+
+    use crate::balancing::{check_transactions, Tolerance};
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn build(journal: &str) -> ParsedLedger {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Ledger);
+        return std::mem::take(bldr.from_string(journal).unwrap_or_else(|e| panic!("{}", e)));
+    }
+
+    fn amounts(xn: &RawTransaction) -> Vec<String> {
+        return xn.postings.iter().map(|p| format!("{} {}", p.acct.path, p.amount.as_ref().unwrap())).collect();
+    }
+
+    #[test]
+    fn can_build_journal() {
+        let pl = build("; groceries
+2023/1/5=2023/01/07 * (1024) Grocery Store  ; weekly
+    ; :food:home:
+    Expenses:Food  $1,052.10
+    ; Receipt: 1234
+    Assets:Checking
+2023-01-06 Landlord
+    Expenses:Rent  -10 USD
+    Assets:Checking  10 USD
+");
+
+        let xns: Vec<&RawTransaction> = pl.transactions().collect();
+        assert_eq!(xns.len(), 2);
+
+        let xn = xns[0];
+        assert_eq!((xn.date.as_str(), xn.anno.as_str(), xn.desc.as_str(), xn.comment.as_str()), ("2023-01-05", "*", "Grocery Store", "weekly"));
+        assert_eq!(xn.meta.get("code"), Some(&MetaValue::String("1024".to_string())));
+        assert_eq!(xn.meta.get("aux-date"), Some(&MetaValue::Date("2023-01-07".to_string())));
+        assert_eq!(xn.tags.iter().map(String::as_str).collect::<Vec<&str>>(), ["food", "home"]);
+        assert_eq!(xn.postings[0].meta.get("Receipt"), Some(&MetaValue::String("1234".to_string())));
+        assert_eq!(amounts(xn), ["Expenses:Food 1052.10 $", "Assets:Checking -1052.10 $"]);
+        assert!(xn.postings[1].inferred);
+
+        assert_eq!((xns[1].date.as_str(), xns[1].anno.as_str()), ("2023-01-06", "txn"));
+        assert!(check_transactions(&pl, &Tolerance::default()).is_empty());
+    }
+
+    #[rstest]
+    #[case ("", "Assets:Checking", "Assets:Checking")]
+    #[case ("alias chk=Assets:Checking\n", "chk", "Assets:Checking")]
+    #[case ("alias a=Assets\n", "a:Checking", "Assets:Checking")]
+    #[case ("alias chk=Assets:Checking\nend aliases\n", "chk", "chk")]
+    #[case ("account Assets:Checking\n    alias chk\n", "chk", "Assets:Checking")]
+    #[case ("apply account Personal\n", "Assets:Checking", "Personal:Assets:Checking")]
+    #[case ("apply account Personal\napply account Joint\n", "Assets", "Personal:Joint:Assets")]
+    #[case ("apply account Personal\nend apply account\n", "Assets:Checking", "Assets:Checking")]
+    fn can_expand_accounts(#[case] directives: &str, #[case] acct: &str, #[case] expanded: &str) {
+        let pl = build(&format!("{directives}2023/01/05 Payee\n    {acct}  $10\n    Equity\n"));
+
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.postings[0].acct.path, expanded);
+    }
+
+    #[test]
+    fn can_handle_declarations() {
+        let pl = build("account Assets:Checking  ; main account
+    note The joint account
+    default
+commodity $
+    format $1,000.00
+payee Grocery Store
+");
+
+        let directives: Vec<&RawDirective> = pl.directives().collect();
+        assert_eq!(directives.len(), 2);
+
+        let RawDirective::Open(open) = directives[0] else { panic!("expected an open: {:?}", directives[0]) };
+        assert_eq!((open.date.as_str(), open.acct.path.as_str(), open.comment.as_str()), ("", "Assets:Checking", "main account"));
+        assert_eq!(open.meta.get("note"), Some(&MetaValue::String("The joint account".to_string())));
+        assert_eq!(open.meta.get("default"), Some(&MetaValue::Bool(true)));
+
+        let RawDirective::Commodity(cmdty) = directives[1] else { panic!("expected a commodity: {:?}", directives[1]) };
+        assert_eq!(cmdty.currency, "$");
+        assert_eq!(cmdty.meta.get("format"), Some(&MetaValue::String("$1,000.00".to_string())));
+    }
+
+    #[rstest]
+    #[case ("    (Budget:Food)  $-52.10\n", 0)]                               // virtual postings need not balance
+    #[case ("    [Budget:Food]  $-52.10\n    [Budget:Available]  $52.10\n", 0)]
+    #[case ("    [Budget:Food]  $-52.10\n    [Budget:Available]\n", 0)]        // inferred among balanced virtual postings
+    #[case ("    [Budget:Food]  $-52.10\n", 1)]
+    fn can_balance_virtual_postings(#[case] virtuals: &str, #[case] errors: usize) {
+        let pl = build(&format!("2023/01/05 Grocery Store\n    Expenses:Food  $52.10\n    Assets:Checking  $-52.10\n{virtuals}"));
+
+        let xn = pl.transactions().next().unwrap();
+        assert!(xn.postings[2..].iter().all(|p| p.kind != PostingKind::Real));
+
+        let errs = check_transactions(&pl, &Tolerance::default());
+        assert_eq!(errs.len(), errors, "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("= /^expenses:food/\n    (Budget:Food)  -1\n", &["(Budget:Food) -52.10 $"])]
+    #[case ("= Expenses:Food\n    [Budget:Food]  -1\n    [Budget:Available]\n", &["[Budget:Food] -52.10 $", "[Budget:Available] 52.10 $"])]
+    #[case ("= /Food/\n    (Charity)  $1.00\n", &["(Charity) 1.00 $"])]
+    #[case ("= /^Income/\n    (Charity)  $1.00\n", &[])]
+    fn can_apply_automated_xact(#[case] auto: &str, #[case] added: &[&str]) {
+        let pl = build(&format!("{auto}2023/01/05 Grocery Store\n    Expenses:Food  $52.10\n    Assets:Checking\n"));
+
+        let xn = pl.transactions().next().unwrap();
+        let postings: Vec<String> = xn.postings[2..].iter().map(|p| {
+            let (open, close) = match p.kind { PostingKind::Virtual => ("(", ")"), PostingKind::BalancedVirtual => ("[", "]"), PostingKind::Real => ("", "") };
+            return format!("{open}{}{close} {}", p.acct.path, p.amount.as_ref().unwrap());
+        }).collect();
+        assert_eq!(postings, added);
+        assert!(check_transactions(&pl, &Tolerance::default()).is_empty());
+    }
+
+    #[test]
+    fn can_skip_periodic_xact() {
+        let pl = build("~ Monthly\n    Expenses:Rent  $1,200.00\n    Assets\n");
+        assert_eq!(pl.transactions().count(), 0);
+    }
+
+    #[test]
+    fn verify_predicate_error() {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Ledger);
+        let err = bldr.from_string("\n= /Food(/\n    (Budget)  -1\n").unwrap_err();
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("expected a LedgerError: {}", err));
+        assert_eq!(err.kind, LedgerErrorKind::InvalidPredicate);
+        assert_eq!(err.pinfo.position.line, 2);
+    }
+
+    #[test]
+    fn can_handle_prices_and_defaults() {
+        let pl = build("Y 2023
+D $1,000.00
+P 2023/01/05 AAPL $150.00 ; closing
+P 1/6 14:30:00 AAPL 151.50
+
+= /^Expenses:Food/
+    (Budget:Food)  -1
+1/5 * Grocery Store
+    Expenses:Food  52.10
+    Assets:Checking
+");
+
+        let prices: Vec<String> = pl.directives().filter_map(|d| match d {
+            RawDirective::Price(p) => Some(format!("{} {} {} {}", p.date, p.currency, p.amount, p.comment)),
+            _ => None,
+        }).collect();
+        assert_eq!(prices, ["2023-01-05 AAPL 150.00 $ closing", "2023-01-06 AAPL 151.50 $ "]);
+
+        // an amount without a commodity still multiplies in an automated posting
+        let xn = pl.transactions().next().unwrap();
+        assert_eq!(xn.date, "2023-01-05");
+        assert_eq!(amounts(xn), ["Expenses:Food 52.10 $", "Assets:Checking -52.10 $", "Budget:Food -52.10 $"]);
+    }
+
+    #[test]
+    fn verify_missing_year_error() {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Ledger);
+        let err = bldr.from_string("\n1/5 Payee\n    Assets:Cash  $1.00\n    Equity\n").unwrap_err();
+
+        let err = err.downcast_ref::<LedgerError>().unwrap_or_else(|| panic!("expected a LedgerError: {}", err));
+        assert_eq!(err.kind, LedgerErrorKind::MissingYear);
+        assert_eq!(err.pinfo.position.line, 2);
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::fmt;
+
+use pta_types::*;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerErrorKind {
+    // A posting to, or close of, an account which was never opened
+    AccountNotOpen,
+    // A reduction matching several lots when the booking method cannot choose between them
+    AmbiguousLotMatch,
+    // A pad which would need to pad more than one commodity
+    AmbiguousPad,
+    // A balance directive which does not match the running balance of its account
+    BalanceAssertion,
+    // Closing an account which still has a balance
+    CloseNonZeroBalance,
+    // Closing an account while any of its sub-accounts remain open
+    CloseWithOpenSubAccounts,
+    // Price directives giving a commodity different prices on the same date
+    ConflictingPrice,
+    // A posting in a currency not allowed by its account's open directive
+    CurrencyConstraint,
+    // A metadata key given more than once for the same item
+    DuplicateMetadata,
+    // An account opened more than once
+    DuplicateOpen,
+    // A posting amount which could not be inferred
+    ElidedAmount,
+    // A posting dated outside the account's open period, or a repeated close
+    InactiveAccount,
+    // A file which includes itself, directly or through other includes
+    IncludeCycle,
+    // An include naming no readable file
+    IncludeNotFound,
+    // A reduction of more units than are held in the lots it matches
+    InsufficientLots,
+    // An account which is not under any of the ledger's root accounts
+    InvalidAccountRoot,
+    // An account directive whose type tag names no account type
+    InvalidAccountType,
+    // An option which is unknown or whose value is malformed
+    InvalidOption,
+    // An automated transaction whose predicate is not a valid regular expression
+    InvalidPredicate,
+    // A quantity whose digit group or decimal marks are misplaced or inconsistent
+    InvalidQuantity,
+    // A lot added at cost without specifying the cost
+    MissingCost,
+    // A date without a year before any default year (Y) directive
+    MissingYear,
+    // A reduction whose cost spec matches none of the account's lots
+    NoMatchingLot,
+    // Arithmetic on amounts exceeded the range of Decimal
+    Overflow,
+    // A transaction with fewer than two postings
+    SinglePosting,
+    // A transaction whose weights do not sum to zero in some commodity
+    Unbalanced,
+    // A pushtag without a matching poptag by the end of its file, or a poptag of a tag not pushed
+    UnbalancedTag,
+    // A commodity used without a commodity directive
+    UndeclaredCommodity,
+    // A plugin directive naming a plugin which is not registered
+    UnknownPlugin,
+    // A pad which no balance assertion needs
+    UnusedPad,
+}
+
+// An error found in a parsed ledger, located by the item which caused it
+#[derive(Debug, Clone)]
+pub struct LedgerError {
+    pub kind: LedgerErrorKind,
+    pub message: String,
+    pub pinfo: ParserInfo,
+}
+
+impl LedgerError {
+    pub fn new(kind: LedgerErrorKind, message: String, pinfo: &ParserInfo) -> LedgerError {
+        return LedgerError { kind, message, pinfo: pinfo.clone() };
+    }
+}
+
+impl fmt::Display for LedgerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.pinfo.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}: {}", self.pinfo.position.line, self.pinfo.position.col, self.message)
+    }
+}
+
+impl std::error::Error for LedgerError {}
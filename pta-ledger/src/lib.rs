@@ -8,6 +8,18 @@ pub extern crate pest_derive;
 pub extern crate pta_parser;
 pub extern crate pta_types;
 
+pub mod account_lifecycle;
+pub mod account_roots;
+pub mod balance_assertions;
+pub mod balancing;
+pub mod booking;
+pub mod currency_constraints;
+pub mod inventory;
 pub mod ledger_builder;
+pub mod ledger_error;
+pub mod padding;
+pub mod plugins;
+pub mod price_db;
+pub mod validation;
 
 pub extern crate log;
\ No newline at end of file
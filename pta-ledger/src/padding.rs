@@ -0,0 +1,177 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeSet;
+
+use pta_types::*;
+
+use crate::balance_assertions::{assertion_tolerance, is_same_or_sub_account};
+use crate::balancing::Tolerance;
+use crate::ledger_error::*;
+
+
+// The balance of the account, including its sub-accounts, before the date
+fn balance_before(pl: &ParsedLedger, acct: &str, commodity: &str, date: &str) -> Option<Decimal> {
+    let mut total = Decimal::zero();
+
+    for xn in pl.transactions().filter(|xn| xn.date.as_str() < date) {
+        for p in xn.postings.iter().filter(|p| is_same_or_sub_account(&p.acct.path, acct)) {
+            if let Some(amount) = p.amount.as_ref().filter(|a| a.commodity == commodity) {
+                total = total.checked_add(&amount.number)?;
+            }
+        }
+    }
+
+    return Some(total);
+}
+
+fn padding_transaction(pad: &RawPad, expected: &Amount, diff: &Amount) -> Option<RawTransaction> {
+    let posting = |acct: &RawAccountDescriptor, amount: Amount| RawPosting {
+        acct: acct.clone(),
+        amount: Some(amount),
+        pinfo: pad.pinfo.clone(),
+        ..Default::default()
+    };
+
+    return Some(RawTransaction {
+        date: pad.date.clone(),
+        anno: "P".to_string(),
+        desc: format!("(Padding inserted for Balance of {} for difference {})", expected, diff),
+        postings: vec![posting(&pad.acct, diff.clone()), posting(&pad.source_acct, diff.checked_neg()?)],
+        pinfo: pad.pinfo.clone(),
+        ..Default::default()
+    });
+}
+
+
+// Resolves each pad directive by adding a transaction, dated on the pad, which moves the amount
+//  needed by the next balance assertion on the padded account from the pad's source account.
+//  A pad applies to the first assertion of each commodity until the account's next pad, and
+//  may only pad a single commodity.
+pub fn resolve_pads(pl: &mut ParsedLedger, tolerance: &Tolerance) -> Vec<LedgerError> {
+    let mut errs = Vec::new();
+
+    let mut pads: Vec<RawPad> = pl.directives()
+        .filter_map(|d| match d { RawDirective::Pad(p) => Some(p.clone()), _ => None })
+        .collect();
+    pads.sort_by(|a, b| a.date.cmp(&b.date));
+
+    let mut balances: Vec<RawBalance> = pl.directives()
+        .filter_map(|d| match d { RawDirective::Balance(b) => Some(b.clone()), _ => None })
+        .collect();
+    balances.sort_by(|a, b| a.date.cmp(&b.date));
+
+    for (i, pad) in pads.iter().enumerate() {
+        let next = pads[i + 1..].iter()
+            .find(|p| p.acct.path == pad.acct.path)
+            .map(|p| p.date.as_str());
+
+        let mut checked: BTreeSet<&str> = BTreeSet::new();
+        let mut needed: Vec<(&Amount, Amount)> = Vec::new();
+
+        let assertions = balances.iter()
+            .filter(|b| b.acct.path == pad.acct.path && b.date > pad.date)
+            .filter(|b| next.is_none_or(|n| b.date.as_str() <= n));
+
+        for bal in assertions {
+            let expected = &bal.amount;
+            if !checked.insert(&expected.commodity) {
+                continue;
+            }
+
+            let Some(diff) = balance_before(pl, &pad.acct.path, &expected.commodity, &bal.date)
+                .and_then(|actual| expected.number.checked_sub(&actual)) else {
+                errs.push(LedgerError::new(LedgerErrorKind::Overflow,
+                    format!("balance of {} overflows", pad.acct.path), &bal.pinfo));
+                continue;
+            };
+
+            if diff.checked_abs().is_none_or(|abs| abs > assertion_tolerance(expected, tolerance)) {
+                needed.push((expected, Amount::new(diff, &expected.commodity)));
+            }
+        }
+
+        match needed.as_slice() {
+            [] => {
+                errs.push(LedgerError::new(LedgerErrorKind::UnusedPad,
+                    format!("pad of {} from {} is not used by any balance assertion", pad.acct.path, pad.source_acct.path),
+                    &pad.pinfo));
+            }
+            [(expected, diff)] => {
+                match padding_transaction(pad, expected, diff) {
+                    Some(xn) => { pl.add_transaction(xn); }
+                    None => {
+                        errs.push(LedgerError::new(LedgerErrorKind::Overflow,
+                            format!("padding of {} overflows", pad.acct.path), &pad.pinfo));
+                    }
+                }
+            }
+            _ => {
+                let amounts: Vec<String> = needed.iter().map(|(_, diff)| diff.to_string()).collect();
+                errs.push(LedgerError::new(LedgerErrorKind::AmbiguousPad,
+                    format!("pad of {} would need amounts in several commodities: {}", pad.acct.path, amounts.join(", ")),
+                    &pad.pinfo));
+            }
+        }
+    }
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod padding_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::balance_assertions::check_balance_assertions;
+    use crate::ledger_builder::LedgerBuilder;
+
+    const LEDGER: &str = "2001-09-11 open Assets:Cash
+2001-09-11 open Equity:Opening
+2001-09-12 * \"deposit\"
+  Assets:Cash    10.00 USD
+  Equity:Opening
+";
+
+    #[rstest]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-14 balance Assets:Cash 100.00 USD\n", &["90.00 USD"])]
+    #[case ("2001-09-11 pad Assets:Cash Equity:Opening\n2001-09-12 balance Assets:Cash 100.00 USD\n", &["100.00 USD"])]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-14 balance Assets:Cash 5.00 USD\n2001-09-15 balance Assets:Cash 5.00 USD\n", &["-5.00 USD"])]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-13 balance Assets:Cash 20.00 USD\n\
+             2001-09-14 pad Assets:Cash Equity:Opening\n2001-09-15 balance Assets:Cash 50.00 USD\n", &["10.00 USD", "30.00 USD"])]
+    fn can_resolve_pad(#[case] pads: &str, #[case] padded: &[&str]) {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(&format!("{LEDGER}{pads}")).unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = resolve_pads(pl, &Tolerance::default());
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+
+        let inserted: Vec<&RawTransaction> = pl.transactions().filter(|xn| xn.anno == "P").collect();
+        let amounts: Vec<String> = inserted.iter().map(|xn| xn.postings[0].amount.as_ref().unwrap().to_string()).collect();
+        assert_eq!(amounts, padded);
+        assert!(inserted.iter().all(|xn| xn.postings[1].acct.path == "Equity:Opening"));
+
+        let errs = check_balance_assertions(pl, &Tolerance::default());
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n", LedgerErrorKind::UnusedPad)]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-14 balance Assets:Cash 10.00 USD\n", LedgerErrorKind::UnusedPad)]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-12 balance Assets:Cash 100.00 USD\n", LedgerErrorKind::UnusedPad)]
+    #[case ("2001-09-12 pad Assets:Cash Equity:Opening\n2001-09-14 balance Assets:Cash 100.00 USD\n2001-09-14 balance Assets:Cash 1.0 BTC\n", LedgerErrorKind::AmbiguousPad)]
+    fn verify_pad_error(#[case] pads: &str, #[case] kind: LedgerErrorKind) {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(&format!("{LEDGER}{pads}")).unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = resolve_pads(pl, &Tolerance::default());
+        assert_eq!(errs.len(), 1, "expected a single error: {:?}", errs);
+        assert_eq!(errs[0].kind, kind);
+        assert_eq!(errs[0].pinfo.position.line, 6);
+        assert!(pl.transactions().all(|xn| xn.anno != "P"));
+    }
+}
@@ -0,0 +1,370 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use log::info;
+
+use pta_types::*;
+
+use crate::inventory::LotCost;
+use crate::ledger_error::*;
+use crate::price_db::implied_price;
+
+
+// A transformation of a ledger's entries, run for each plugin directive which names it. The
+//  entries are ordered by date; those returned replace them.
+pub trait LedgerPlugin {
+    fn run(self: &Self, entries: Vec<LedgerEntry>, options: &LedgerOptions, config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>);
+}
+
+// The plugins which plugin directives may name, by name
+#[derive(Default)]
+pub struct PluginRegistry {
+    plugins: BTreeMap<String, Box<dyn LedgerPlugin>>,
+}
+
+impl PluginRegistry {
+    // A registry of the built-in plugins, under the names beancount gives them
+    pub fn with_builtins() -> PluginRegistry {
+        let mut registry = PluginRegistry::default();
+        registry.register("beancount.plugins.auto_accounts", Box::new(AutoAccounts));
+        registry.register("beancount.plugins.check_commodity", Box::new(CheckCommodity));
+        registry.register("beancount.plugins.implicit_prices", Box::new(ImplicitPrices));
+        registry.register("beancount.plugins.unique_prices", Box::new(UniquePrices));
+        return registry;
+    }
+
+    // Registers the plugin under the name, replacing any plugin registered under it
+    pub fn register(self: &mut Self, name: &str, plugin: Box<dyn LedgerPlugin>) {
+        self.plugins.insert(name.to_string(), plugin);
+    }
+
+    pub fn get(self: &Self, name: &str) -> Option<&dyn LedgerPlugin> {
+        return self.plugins.get(name).map(|p| p.as_ref());
+    }
+
+    // Runs the plugins named by the ledger's plugin directives, in order, returning the errors
+    //  they report and an error for each name not registered
+    pub fn run_plugins(self: &Self, pl: &mut ParsedLedger) -> Vec<LedgerError> {
+        let mut errs = Vec::new();
+
+        let plugins: Vec<RawPlugin> = pl.plugins().cloned().collect();
+        for directive in plugins {
+            let Some(plugin) = self.get(&directive.name) else {
+                errs.push(LedgerError::new(LedgerErrorKind::UnknownPlugin,
+                    format!("plugin {} is not registered", directive.name), &directive.pinfo));
+                continue;
+            };
+
+            info!("running plugin {}", directive.name);
+            let entries = pl.take_entries();
+            let (entries, plugin_errs) = plugin.run(entries, pl.options(), directive.config.as_deref());
+            pl.add_entries(entries);
+            errs.extend(plugin_errs);
+        }
+
+        return errs;
+    }
+}
+
+
+// The accounts named by the entry, with their descriptors
+fn accounts_of(entry: &LedgerEntry) -> Vec<&RawAccountDescriptor> {
+    match entry {
+        LedgerEntry::Transaction(xn) => { return xn.postings.iter().map(|p| &p.acct).collect(); }
+        LedgerEntry::Directive(d) => {
+            return match d {
+                RawDirective::Open(o) => vec![&o.acct],
+                RawDirective::Close(c) => vec![&c.acct],
+                RawDirective::Balance(b) => vec![&b.acct],
+                RawDirective::Note(n) => vec![&n.acct],
+                RawDirective::Document(doc) => vec![&doc.acct],
+                RawDirective::Pad(p) => vec![&p.acct, &p.source_acct],
+                RawDirective::Commodity(_) | RawDirective::Event(_) | RawDirective::Price(_) => vec![],
+            };
+        }
+    }
+}
+
+// The commodities named by the entry, with the parser info of the item naming each
+fn commodities_of(entry: &LedgerEntry) -> Vec<(&str, &ParserInfo)> {
+    let mut cmdties = Vec::new();
+
+    match entry {
+        LedgerEntry::Transaction(xn) => {
+            for p in &xn.postings {
+                let amounts = [
+                    p.amount.as_ref(),
+                    p.cost.as_ref().and_then(|c| c.amount.as_ref()),
+                    p.price.as_ref().map(|pr| &pr.amount),
+                ];
+                cmdties.extend(amounts.into_iter().flatten().map(|a| (a.commodity.as_str(), &p.pinfo)));
+            }
+        }
+        LedgerEntry::Directive(d) => {
+            match d {
+                RawDirective::Open(o) => { cmdties.extend(o.currencies.iter().map(|c| (c.as_str(), &o.pinfo))); }
+                RawDirective::Balance(b) => { cmdties.push((b.amount.commodity.as_str(), &b.pinfo)); }
+                RawDirective::Price(p) => {
+                    cmdties.push((p.currency.as_str(), &p.pinfo));
+                    cmdties.push((p.amount.commodity.as_str(), &p.pinfo));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    cmdties.retain(|(c, _)| !c.is_empty());
+    return cmdties;
+}
+
+
+// Opens each account which is used but never opened, on the date of its first use
+pub struct AutoAccounts;
+
+impl LedgerPlugin for AutoAccounts {
+    fn run(self: &Self, mut entries: Vec<LedgerEntry>, _options: &LedgerOptions, _config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>) {
+        let opened: BTreeSet<&str> = entries.iter()
+            .filter_map(|e| match e { LedgerEntry::Directive(RawDirective::Open(o)) => Some(o.acct.path.as_str()), _ => None })
+            .collect();
+
+        // entries are ordered by date, so the first use of an account is the earliest
+        let mut opens: BTreeMap<&str, RawOpen> = BTreeMap::new();
+        for entry in &entries {
+            for acct in accounts_of(entry).into_iter().filter(|a| !opened.contains(a.path.as_str())) {
+                opens.entry(&acct.path).or_insert_with(|| RawOpen {
+                    date: entry.date().to_string(),
+                    acct: acct.clone(),
+                    pinfo: acct.pinfo.clone(),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let opens: Vec<LedgerEntry> = opens.into_values().map(|o| LedgerEntry::Directive(RawDirective::Open(o))).collect();
+        entries.extend(opens);
+        entries.sort_by(|a, b| a.date().cmp(b.date()));
+
+        return (entries, Vec::new());
+    }
+}
+
+// Reports each commodity which is used without a commodity directive, at its first use
+pub struct CheckCommodity;
+
+impl LedgerPlugin for CheckCommodity {
+    fn run(self: &Self, entries: Vec<LedgerEntry>, _options: &LedgerOptions, _config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>) {
+        let mut errs = Vec::new();
+
+        let mut seen: BTreeSet<&str> = entries.iter()
+            .filter_map(|e| match e { LedgerEntry::Directive(RawDirective::Commodity(c)) => Some(c.currency.as_str()), _ => None })
+            .collect();
+
+        for (cmdty, pinfo) in entries.iter().flat_map(commodities_of) {
+            if seen.insert(cmdty) {
+                errs.push(LedgerError::new(LedgerErrorKind::UndeclaredCommodity,
+                    format!("commodity {cmdty} is used but not declared"), pinfo));
+            }
+        }
+
+        return (entries, errs);
+    }
+}
+
+// Adds a price directive for the per-unit price or cost of each posting which has one, unless
+//  the same price is already given on the date
+pub struct ImplicitPrices;
+
+impl LedgerPlugin for ImplicitPrices {
+    fn run(self: &Self, mut entries: Vec<LedgerEntry>, _options: &LedgerOptions, _config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>) {
+        let key = |p: &RawPrice| (p.date.clone(), p.currency.clone(), p.amount.commodity.clone(), p.amount.number);
+
+        let mut priced: BTreeSet<(String, String, String, Decimal)> = entries.iter()
+            .filter_map(|e| match e { LedgerEntry::Directive(RawDirective::Price(p)) => Some(key(p)), _ => None })
+            .collect();
+
+        let mut prices = Vec::new();
+        for entry in &entries {
+            let LedgerEntry::Transaction(xn) = entry else {
+                continue;
+            };
+
+            for p in &xn.postings {
+                let Some(units) = p.amount.as_ref().filter(|a| !a.commodity.is_empty() && !a.is_zero()) else {
+                    continue;
+                };
+
+                let per_unit_cost = || {
+                    let cost = p.cost.as_ref()?;
+                    match cost.kind {
+                        CostKind::PerUnit => { return cost.amount.clone(); }
+                        CostKind::Total => {
+                            let total = cost.total_for(&units.number)?;
+                            return Some(Amount::new(LotCost::per_unit(&total.number, &units.number)?, &total.commodity));
+                        }
+                    }
+                };
+
+                let Some(amount) = implied_price(p).or_else(per_unit_cost) else {
+                    continue;
+                };
+
+                let price = RawPrice { date: xn.date.clone(), currency: units.commodity.clone(), amount, pinfo: p.pinfo.clone(), ..Default::default() };
+                if priced.insert(key(&price)) {
+                    prices.push(LedgerEntry::Directive(RawDirective::Price(price)));
+                }
+            }
+        }
+
+        entries.extend(prices);
+        entries.sort_by(|a, b| a.date().cmp(b.date()));
+
+        return (entries, Vec::new());
+    }
+}
+
+// Reports price directives which give a commodity different prices in the same currency on
+//  the same date
+pub struct UniquePrices;
+
+impl LedgerPlugin for UniquePrices {
+    fn run(self: &Self, entries: Vec<LedgerEntry>, _options: &LedgerOptions, _config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>) {
+        let mut errs = Vec::new();
+        let mut first: BTreeMap<(&str, &str, &str), &RawPrice> = BTreeMap::new();
+
+        for entry in &entries {
+            let LedgerEntry::Directive(RawDirective::Price(p)) = entry else {
+                continue;
+            };
+
+            let earlier = *first.entry((&p.date, &p.currency, &p.amount.commodity)).or_insert(p);
+            if earlier.amount.number != p.amount.number {
+                errs.push(LedgerError::new(LedgerErrorKind::ConflictingPrice,
+                    format!("price of {} on {} is {}, but was given as {}", p.currency, p.date, p.amount, earlier.amount),
+                    &p.pinfo));
+            }
+        }
+
+        return (entries, errs);
+    }
+}
+
+
+
+#[cfg(test)]
+mod plugins_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    fn run(ledger: &str) -> (ParsedLedger, Vec<LedgerError>) {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(ledger).unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = PluginRegistry::with_builtins().run_plugins(pl);
+        return (std::mem::take(pl), errs);
+    }
+
+    #[test]
+    fn can_auto_open_accounts() {
+        let (pl, errs) = run("plugin \"beancount.plugins.auto_accounts\"\n\
+            2001-09-11 open Assets:Cash\n\
+            2001-09-12 * \"deposit\"\n  Assets:Cash    1.00 USD\n  Equity:Opening\n\
+            2001-09-13 balance Assets:Savings 0.00 USD\n");
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+
+        let opens: Vec<(&str, &str)> = pl.directives()
+            .filter_map(|d| match d { RawDirective::Open(o) => Some((o.date.as_str(), o.acct.path.as_str())), _ => None })
+            .collect();
+        assert_eq!(opens, vec![("2001-09-11", "Assets:Cash"), ("2001-09-12", "Equity:Opening"), ("2001-09-13", "Assets:Savings")]);
+    }
+
+    #[test]
+    fn can_keep_source_order() {
+        let (pl, errs) = run("plugin \"beancount.plugins.auto_accounts\"\n\
+            2001-09-12 open Assets:Cash\n\
+            2001-09-13 * \"b\"\n  Assets:Cash    1.00 USD\n  Equity\n\
+            2001-09-12 * \"a\"\n  Assets:Cash    1.00 USD\n  Equity\n\
+            2001-09-13 balance Assets:Cash 1.00 USD\n\
+            2001-09-13 * \"c\"\n  Assets:Cash    1.00 USD\n  Equity\n");
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+
+        assert_eq!(pl.transactions().map(|xn| xn.desc.as_str()).collect::<Vec<_>>(), vec!["b", "a", "c"]);
+        // the open made for Equity takes the position of its first use, in "a"
+        let lines: Vec<usize> = pl.directives().map(|d| d.pinfo().position.line).collect();
+        assert_eq!(lines, vec![2, 8, 9]);
+        assert!(matches!(pl.directives().nth(1), Some(RawDirective::Open(o)) if o.acct.path == "Equity"));
+    }
+
+    #[rstest]
+    #[case ("", &["BTC", "USD"])]
+    #[case ("2001-09-11 commodity USD\n", &["BTC"])]
+    #[case ("2001-09-11 commodity USD\n2001-09-11 commodity BTC\n", &[])]
+    fn can_check_commodities(#[case] commodities: &str, #[case] undeclared: &[&str]) {
+        let (_, errs) = run(&format!("plugin \"beancount.plugins.check_commodity\"\n{commodities}\
+            2001-09-12 * \"buy\"\n  Assets:Broker    1.0 BTC {{400.00 USD}}\n  Assets:Cash\n\
+            2001-09-13 price BTC 500.00 USD\n"));
+
+        assert!(errs.iter().all(|e| e.kind == LedgerErrorKind::UndeclaredCommodity));
+        let cmdties: Vec<&str> = errs.iter().map(|e| e.message.split(' ').nth(1).unwrap()).collect();
+        assert_eq!(cmdties, undeclared);
+    }
+
+    #[test]
+    fn can_add_implicit_prices() {
+        let (pl, errs) = run("plugin \"beancount.plugins.implicit_prices\"\n\
+            2001-09-12 price BTC 400.00 USD\n\
+            2001-09-12 * \"buy\"\n  Assets:Broker    2.0 BTC {400.00 USD}\n  Assets:Cash\n\
+            2001-09-13 * \"buy\"\n  Assets:Broker    2.00 ETH @@ 300.00 USD\n  Assets:Cash\n");
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+
+        let prices: Vec<String> = pl.directives()
+            .filter_map(|d| match d { RawDirective::Price(p) => Some(format!("{} {} {}", p.date, p.currency, p.amount)), _ => None })
+            .collect();
+        assert_eq!(prices, vec!["2001-09-12 BTC 400.00 USD", "2001-09-13 ETH 150.0000 USD"]);
+    }
+
+    #[rstest]
+    #[case ("2001-09-12 price BTC 400.00 USD\n2001-09-12 price BTC 400.0 USD\n", 0)]
+    #[case ("2001-09-12 price BTC 400.00 USD\n2001-09-13 price BTC 500.00 USD\n", 0)]
+    #[case ("2001-09-12 price BTC 400.00 USD\n2001-09-12 price BTC 0.50 EUR\n", 0)]
+    #[case ("2001-09-12 price BTC 400.00 USD\n2001-09-12 price BTC 500.00 USD\n", 1)]
+    fn can_check_unique_prices(#[case] prices: &str, #[case] errors: usize) {
+        let (_, errs) = run(&format!("plugin \"beancount.plugins.unique_prices\"\n{prices}"));
+        assert_eq!(errs.len(), errors, "unexpected errors: {:?}", errs);
+        assert!(errs.iter().all(|e| e.kind == LedgerErrorKind::ConflictingPrice && e.pinfo.position.line == 3));
+    }
+
+    struct TagAll;
+
+    impl LedgerPlugin for TagAll {
+        fn run(self: &Self, mut entries: Vec<LedgerEntry>, _options: &LedgerOptions, config: Option<&str>) -> (Vec<LedgerEntry>, Vec<LedgerError>) {
+            for entry in entries.iter_mut() {
+                if let LedgerEntry::Transaction(xn) = entry {
+                    xn.tags.insert(config.unwrap_or("all").to_string());
+                }
+            }
+            return (entries, Vec::new());
+        }
+    }
+
+    #[test]
+    fn can_run_registered_plugins() {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string("plugin \"tag_all\" \"reviewed\"\nplugin \"no.such.plugin\"\n\
+            2001-09-12 * \"deposit\"\n  Assets:Cash    1.00 USD\n  Equity\n").unwrap_or_else(|e| panic!("{}", e));
+
+        let mut registry = PluginRegistry::with_builtins();
+        registry.register("tag_all", Box::new(TagAll));
+
+        let errs = registry.run_plugins(pl);
+        assert_eq!(errs.len(), 1, "unexpected errors: {:?}", errs);
+        assert_eq!(errs[0].kind, LedgerErrorKind::UnknownPlugin);
+        assert_eq!(errs[0].pinfo.position.line, 2);
+        assert_eq!(pl.transactions_tagged("reviewed").count(), 1);
+    }
+}
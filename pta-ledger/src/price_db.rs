@@ -0,0 +1,169 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use pta_types::*;
+
+
+// The number of decimal places kept when inverting a rate
+const INVERSE_SCALE: u32 = 12;
+
+
+// The per-unit price implied by the posting's price annotation (@ or @@), if any
+pub fn implied_price(p: &RawPosting) -> Option<Amount> {
+    let (units, price) = (p.amount.as_ref()?, p.price.as_ref()?);
+    if units.commodity.is_empty() || units.is_zero() {
+        return None;
+    }
+
+    let number = match price.kind {
+        PriceKind::PerUnit => price.amount.number,
+        PriceKind::Total => price.amount.number.checked_abs()?
+            .checked_div(&units.number.checked_abs()?, price.amount.number.scale() + units.number.scale())?,
+    };
+
+    return Some(Amount::new(number, &price.amount.commodity));
+}
+
+
+// The rates of each commodity in others, by date. Rates are stored as given (base in quote) and
+//  inverted on lookup; a later price on the same date replaces an earlier one.
+#[derive(Debug, Default, Clone)]
+pub struct PriceDb {
+    rates: BTreeMap<(String, String), BTreeMap<String, Decimal>>,
+}
+
+impl PriceDb {
+    // A database of the ledger's price directives
+    pub fn from_ledger(pl: &ParsedLedger) -> PriceDb {
+        let mut db = PriceDb::default();
+
+        for d in pl.directives() {
+            if let RawDirective::Price(p) = d {
+                db.add(&p.date, &p.currency, &p.amount);
+            }
+        }
+
+        return db;
+    }
+
+    // Adds the per-unit prices implied by the price annotations (@ and @@) of the ledger's postings
+    pub fn add_implicit_prices(self: &mut Self, pl: &ParsedLedger) {
+        for xn in pl.transactions() {
+            for p in &xn.postings {
+                if let (Some(units), Some(rate)) = (&p.amount, implied_price(p)) {
+                    self.add(&xn.date, &units.commodity, &rate);
+                }
+            }
+        }
+    }
+
+    // Records the rate of one unit of 'base' on the date
+    pub fn add(self: &mut Self, date: &str, base: &str, rate: &Amount) {
+        self.rates.entry((base.to_string(), rate.commodity.clone())).or_default()
+            .insert(date.to_string(), rate.number);
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        return self.rates.is_empty();
+    }
+
+    // The rate of 'base' in 'quote' on the date, from the most recent price on or before the date
+    //  in either direction
+    pub fn rate(self: &Self, base: &str, quote: &str, date: &str) -> Option<Decimal> {
+        if base == quote {
+            return Some(Decimal::new(1, 0));
+        }
+
+        let latest = |b: &str, q: &str| self.rates.get(&(b.to_string(), q.to_string()))
+            .and_then(|by_date| by_date.range(..=date.to_string()).next_back());
+
+        let direct = latest(base, quote);
+        let inverse = latest(quote, base).filter(|(_, r)| !r.is_zero());
+
+        match (direct, inverse) {
+            (Some((d, r)), Some((i, _))) if d >= i => { return Some(*r); }
+            (Some((_, r)), None) => { return Some(*r); }
+            (_, Some((_, r))) => { return Decimal::new(1, 0).checked_div(r, INVERSE_SCALE); }
+            (None, None) => { return None; }
+        }
+    }
+
+    // The amount's value in 'quote' on the date
+    pub fn convert(self: &Self, amount: &Amount, quote: &str, date: &str) -> Option<Amount> {
+        let rate = self.rate(&amount.commodity, quote, date)?;
+        return Some(Amount::new(amount.number.checked_mul(&rate)?, quote));
+    }
+}
+
+
+
+#[cfg(test)]
+mod price_db_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    const LEDGER: &str = "2001-09-11 price BTC 400.00 USD
+2001-09-13 price BTC 500.00 USD
+2001-09-13 price EUR 1.25 USD
+2001-09-15 price USD 0.50 EUR
+2001-09-14 * \"buy\"
+  Assets:Broker    2.00 ETH @@ 300.00 USD
+  Assets:Cash
+2001-09-16 * \"buy\"
+  Assets:Broker    1.0 BTC @ 450.00 USD
+  Assets:Cash
+";
+
+    fn price_db(implicit: bool) -> PriceDb {
+        let mut bldr = LedgerBuilder::default();
+        let pl = bldr.from_string(LEDGER).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut db = PriceDb::from_ledger(pl);
+        if implicit {
+            db.add_implicit_prices(pl);
+        }
+        return db;
+    }
+
+    #[rstest]
+    #[case ("BTC", "USD", "2001-09-11", Some("400.00"))]
+    #[case ("BTC", "USD", "2001-09-12", Some("400.00"))]      // nearest earlier
+    #[case ("BTC", "USD", "2001-09-13", Some("500.00"))]
+    #[case ("BTC", "USD", "2001-09-10", None)]
+    #[case ("USD", "BTC", "2001-09-14", Some("0.002"))]       // inverse
+    #[case ("EUR", "USD", "2001-09-14", Some("1.25"))]
+    #[case ("USD", "EUR", "2001-09-14", Some("0.8"))]
+    #[case ("USD", "EUR", "2001-09-15", Some("0.50"))]        // most recent direction wins
+    #[case ("EUR", "USD", "2001-09-15", Some("2"))]
+    #[case ("USD", "USD", "2001-09-15", Some("1"))]
+    #[case ("ETH", "USD", "2001-09-20", None)]
+    fn can_lookup_rate(#[case] base: &str, #[case] quote: &str, #[case] date: &str, #[case] expected: Option<&str>) {
+        let db = price_db(false);
+        let expected = expected.map(|e| e.parse::<Decimal>().unwrap());
+        assert_eq!(db.rate(base, quote, date), expected);
+    }
+
+    #[rstest]
+    #[case ("ETH", "USD", "2001-09-14", Some("150.00"))]      // from @@ total price
+    #[case ("BTC", "USD", "2001-09-16", Some("450.00"))]      // from @ price, replacing the directive's
+    #[case ("BTC", "USD", "2001-09-15", Some("500.00"))]
+    fn can_lookup_implicit_rate(#[case] base: &str, #[case] quote: &str, #[case] date: &str, #[case] expected: Option<&str>) {
+        let db = price_db(true);
+        let expected = expected.map(|e| e.parse::<Decimal>().unwrap());
+        assert_eq!(db.rate(base, quote, date), expected);
+    }
+
+    #[test]
+    fn can_convert_amount() {
+        let db = price_db(false);
+        let converted = db.convert(&Amount::new("1.5".parse().unwrap(), "BTC"), "USD", "2001-09-13");
+        assert_eq!(converted.map(|a| a.to_string()), Some("750.000 USD".to_string()));
+        assert!(db.convert(&Amount::new("1.5".parse().unwrap(), "BTC"), "EUR", "2001-09-13").is_none());
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use pta_parser::parsers::Dialect;
+use pta_types::*;
+
+use crate::account_lifecycle;
+use crate::account_roots;
+use crate::balance_assertions;
+use crate::balancing::{self, Tolerance};
+use crate::currency_constraints;
+use crate::ledger_error::*;
+
+
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    pub tolerance: Tolerance,
+    // The names of the root accounts under which all accounts must be; only beancount has them
+    pub root_accounts: Vec<String>,
+    // The dialect of the ledger, which decides the checks particular to a dialect
+    pub dialect: Dialect,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        return ValidationOptions::from_options(&LedgerOptions::default(), Dialect::default());
+    }
+}
+
+impl ValidationOptions {
+    // The validation options set by the options of a ledger in the dialect
+    pub fn from_options(options: &LedgerOptions, dialect: Dialect) -> ValidationOptions {
+        return ValidationOptions {
+            tolerance: Tolerance::from_options(options),
+            root_accounts: options.root_accounts().iter().map(|r| r.to_string()).collect(),
+            dialect,
+        };
+    }
+}
+
+// Runs all checks over the ledger, returning every error found
+pub fn validate(pl: &ParsedLedger, options: &ValidationOptions) -> Vec<LedgerError> {
+    let mut errs = balancing::check_transactions(pl, &options.tolerance);
+    errs.extend(balance_assertions::check_balance_assertions(pl, &options.tolerance));
+    errs.extend(balance_assertions::check_posting_assertions(pl, &options.tolerance));
+    // the journals of ledger-cli and hledger need not declare their accounts
+    let require_open = !matches!(options.dialect, Dialect::Ledger | Dialect::Hledger);
+    errs.extend(account_lifecycle::check_account_lifecycles(pl, require_open));
+    if options.dialect == Dialect::Beancount {
+        errs.extend(account_roots::check_account_roots(pl, &options.root_accounts));
+    }
+    errs.extend(currency_constraints::check_currency_constraints(pl));
+
+    return errs;
+}
+
+
+
+#[cfg(test)]
+mod validation_tests {
+    use super::*;
+    use rstest::rstest;
+
+    use crate::ledger_builder::LedgerBuilder;
+
+    const LEDGER: &str = "2001-09-11 open Assets:Cash
+2001-09-11 open Equity
+2001-09-12 * \"deposit\"
+  Assets:Cash    1.01 USD
+  Equity    -1.00 USD
+";
+
+    #[rstest]
+    #[case ("", Dialect::Beancount, 1)]
+    #[case ("option \"inferred_tolerance_multiplier\" \"2\"\n", Dialect::Beancount, 0)]
+    #[case ("option \"name_equity\" \"Capital\"\n", Dialect::Beancount, 2)]
+    #[case ("option \"name_equity\" \"Capital\"\n", Dialect::Generic, 1)]
+    fn can_validate_with_ledger_options(#[case] options: &str, #[case] dialect: Dialect, #[case] errors: usize) {
+        let mut bldr = LedgerBuilder::with_dialect(dialect);
+        let pl = bldr.from_string(&format!("{options}{LEDGER}")).unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = validate(pl, &ValidationOptions::from_options(pl.options(), dialect));
+        assert_eq!(errs.len(), errors, "unexpected errors: {:?}", errs);
+    }
+
+    #[rstest]
+    #[case (Dialect::Ledger, "2001/09/12 * Deposit\n    assets:cash  $1.00\n    equity\n")]
+    #[case (Dialect::Ledger, "apply account Budget\n2001/09/12 Groceries\n    Food  $-10.00\n    Cash\nend apply account\n")]
+    #[case (Dialect::Hledger, "2001-09-12 Deposit\n    assets:cash  $1.00\n    equity\n")]
+    fn can_validate_journal_without_declarations(#[case] dialect: Dialect, #[case] journal: &str) {
+        let mut bldr = LedgerBuilder::with_dialect(dialect);
+        let pl = bldr.from_string(journal).unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = validate(pl, &ValidationOptions::from_options(pl.options(), dialect));
+        assert!(errs.is_empty(), "unexpected errors: {:?}", errs);
+    }
+
+    #[test]
+    fn verify_generic_ledger_requires_open() {
+        let mut bldr = LedgerBuilder::with_dialect(Dialect::Generic);
+        let pl = bldr.from_string("2001-09-12 * \"deposit\"\n  assets:cash    1.00 USD\n  equity\n").unwrap_or_else(|e| panic!("{}", e));
+
+        let errs = validate(pl, &ValidationOptions::from_options(pl.options(), Dialect::Generic));
+        assert_eq!(errs.iter().filter(|e| e.kind == LedgerErrorKind::AccountNotOpen).count(), 2, "unexpected errors: {:?}", errs);
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+#[cfg(test)] use super::*;
+#[cfg(test)] use rstest::rstest;
+
+
+
+#[cfg(test)]
+mod journal {
+    use super::*;
+
+    fn parse(r: hledger::Rule, content: &str) -> Pairs<'_, hledger::Rule> {
+        return hledger::Parser::parse(r, content)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    #[rstest]
+    #[case ("2023/01/05")]
+    #[case ("2023-1-5")]
+    #[case ("2023.01.05")]
+    #[case ("1/5")]                  // partial; the year is that of the last Y directive
+    #[case ("12-31")]
+    fn can_parse_date(#[case] date: &str) {
+        let pair = parse(hledger::Rule::date, date).next().unwrap();
+        assert_eq!(pair.as_str(), date);
+    }
+
+    #[rstest]
+    #[case ("$1,000.50")]
+    #[case ("1.000,50 EUR")]
+    #[case ("-1 000")]
+    #[case ("1 000 000.9455 EUR")]
+    #[case ("1,5 EUR")]
+    #[case ("1.000.000 EUR")]
+    fn can_parse_amount(#[case] amount: &str) {
+        let pair = parse(hledger::Rule::amount, amount).next().unwrap();
+        assert_eq!(pair.as_str(), amount);
+    }
+
+    #[rstest]
+    #[case ("1,,0", "1")]            // the marks stop the quantity
+    #[case ("1,00,000", "1,00")]     // digit groups have three digits
+    #[case ("1 0000", "1")]
+    #[case ("1 5", "1")]
+    fn can_parse_partial_quantity(#[case] quantity: &str, #[case] parsed: &str) {
+        let pair = parse(hledger::Rule::quantity, quantity).next().unwrap();
+        assert_eq!(pair.as_str(), parsed);
+    }
+
+    #[rstest]
+    #[case ("  Assets:Checking  $10.00 = $110.00\n")]
+    #[case ("  Assets:Checking  $10.00 == $110.00\n")]
+    #[case ("  Assets:Checking  $10.00 =* $110.00\n")]
+    #[case ("  Assets:Checking  $10.00 ==* $110.00 ; checked\n")]
+    #[case ("  Assets:Brokerage  10 AAPL @ $160.00 = 20 AAPL\n")]
+    fn can_parse_posting(#[case] posting: &str) {
+        assert!(parse(hledger::Rule::posting, posting).len() > 0);
+    }
+
+    #[rstest]
+    #[case ("  (Budget:Food)  *-1\n")]                                   // multiplier
+    #[case ("  (Budget:Food)  *0.5 ; half\n")]
+    #[case ("  Assets:Savings  $10.00\n")]
+    fn can_parse_auto_posting(#[case] posting: &str) {
+        assert!(parse(hledger::Rule::auto_posting, posting).len() > 0);
+    }
+
+    #[rstest]
+    #[case (hledger::Rule::price_directive, "P 2023/01/05 AAPL $150.00\n")]
+    #[case (hledger::Rule::price_directive, "P 2023-01-05 \"ACME Corp\" 10 EUR ; closing\n")]
+    #[case (hledger::Rule::price_directive, "P 2023.01.05 14:30 AAPL $150.00\n")]
+    #[case (hledger::Rule::default_commodity, "D $1,000.00\n")]
+    #[case (hledger::Rule::default_year, "Y 2023\n")]
+    #[case (hledger::Rule::default_year, "Y2023\n")]
+    #[case (hledger::Rule::default_year, "year 2023\n")]
+    #[case (hledger::Rule::decimal_mark_directive, "decimal-mark ,\n")]
+    #[case (hledger::Rule::account_directive, "account Assets:Checking  ; type: A\n")]
+    fn can_parse_directive(#[case] r: hledger::Rule, #[case] directive: &str) {
+        assert!(parse(r, directive).len() > 0);
+    }
+
+    #[rstest]
+    #[case ("Y 23\n", "expected year")]                                 // a year has 4 digits
+    #[case ("decimal-mark ;\n", "expected decimal_mark")]
+    #[case ("2023/01/05 Payee\n  Assets:Checking  $10.00 =\n  Equity\n", "expected amount")]   // an assertion needs an amount
+    #[case ("2023/01/05 Payee\n  Expenses:Food  $1,,0\n  Equity\n", "2:20")]
+    #[case ("2023/01/05 Payee\n  Expenses:Food  *-1\n  Equity\n", "2:18")]        // a multiplier outside an automated transaction
+    fn verify_journal_error(#[case] journal: &str, #[case] msg: &str) {
+        let err = hledger::Parser::parse(hledger::Rule::hledger_journal, journal).unwrap_err();
+        assert!(err.to_string().contains(msg), "missing '{}' in '{}'", msg, err);
+    }
+
+    #[test]
+    fn can_parse_journal() {
+        let journal = "; An hledger journal
+decimal-mark .
+Y 2023
+D $1,000.00
+account Assets:Checking  ; type: A
+P 2023/01/05 AAPL $150.00
+
+= /^Expenses:Food/
+    (Budget:Food)  *-1
+
+1/5 * Grocery Store
+    Expenses:Food  52.10
+    Assets:Checking  $-52.10 = $947.90
+";
+        assert!(parse(hledger::Rule::hledger_journal, journal).len() > 0);
+    }
+}
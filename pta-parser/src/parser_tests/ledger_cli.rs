@@ -0,0 +1,118 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+#[cfg(test)] use super::*;
+#[cfg(test)] use rstest::rstest;
+
+
+
+#[cfg(test)]
+mod journal {
+    use super::*;
+
+    fn parse(r: ledger::Rule, content: &str) -> Pairs<'_, ledger::Rule> {
+        return ledger::Parser::parse(r, content)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    #[rstest]
+    #[case ("$10.00")]
+    #[case ("$ 10")]
+    #[case ("-$1,000.50")]
+    #[case ("$-10")]
+    #[case ("10 USD")]
+    #[case ("-0.5 BTC")]
+    #[case ("10 \"ACME Corp\"")]
+    #[case ("42")]
+    fn can_parse_amount(#[case] amount: &str) {
+        let pair = parse(ledger::Rule::amount, amount).next().unwrap();
+        assert_eq!(pair.as_str(), amount);
+    }
+
+    #[rstest]
+    #[case ("  Assets:Checking  $10.00\n")]
+    #[case ("\tExpenses:Food and Drink\t-10 USD ; lunch\n")]
+    #[case ("  Assets:Checking\n")]                                     // elided amount
+    #[case ("  * Assets:Checking  $10.00\n")]                           // cleared posting
+    #[case ("  (Budget:Food)  $-10.00\n")]                              // virtual
+    #[case ("  [Savings:Vacation]  $10.00\n")]                          // balanced virtual
+    #[case ("  Assets:Brokerage  10 AAPL {$150.00} [2023/01/05] @ $160.00\n")]
+    #[case ("  Assets:Brokerage  10 AAPL {{$1500.00}} @@ $1600.00\n")]
+    fn can_parse_posting(#[case] posting: &str) {
+        assert!(parse(ledger::Rule::posting, posting).len() > 0);
+    }
+
+    #[rstest]
+    #[case ("2023/01/05 * Grocery Store  ; weekly\n  Expenses:Food  $52.10\n  Assets:Checking\n")]
+    #[case ("2023/1/5 Grocery Store\n  ; :food:\n  Expenses:Food  $52.10\n  ; Receipt: 1234\n  Assets:Checking\n")]
+    #[case ("2023-01-05=2023/01/07 ! (1024) Landlord\n  Expenses:Rent  $1,200.00\n  Assets:Checking  $-1,200.00\n")]
+    #[case ("2023/01/05\n  Expenses:Misc  $1\n  Assets:Checking\n")]
+    #[case ("1/5 Grocery Store\n  Expenses:Food  $52.10\n  Assets:Checking\n")]   // the year of a Y directive
+    fn can_parse_xact(#[case] xact: &str) {
+        assert!(parse(ledger::Rule::xact, xact).len() > 0);
+    }
+
+    #[rstest]
+    #[case (ledger::Rule::account_directive, "account Assets:Checking\n")]
+    #[case (ledger::Rule::account_directive, "account Expenses:Food\n  note Groceries and dining\n  alias food\n")]
+    #[case (ledger::Rule::commodity_directive, "commodity $\n  format $1,000.00\n")]
+    #[case (ledger::Rule::payee_directive, "payee Grocery Store ; the corner shop\n")]
+    #[case (ledger::Rule::alias_directive, "alias chk=Assets:Checking\n")]
+    #[case (ledger::Rule::apply_account, "apply account Personal\n")]
+    #[case (ledger::Rule::end_apply_account, "end apply account\n")]
+    #[case (ledger::Rule::price_directive, "P 2023/01/05 AAPL $150.00\n")]
+    #[case (ledger::Rule::price_directive, "P 2023/01/05 14:30:00 \"ACME Corp\" 1.5 EUR ; closing\n")]
+    #[case (ledger::Rule::default_commodity, "D $1,000.00\n")]
+    #[case (ledger::Rule::default_year, "Y 2023\n")]
+    #[case (ledger::Rule::default_year, "year 2023\n")]
+    #[case (ledger::Rule::auto_xact, "= /^Expenses:Food/\n  (Budget:Food)  -1\n")]
+    #[case (ledger::Rule::periodic_xact, "~ Monthly\n  Expenses:Rent  $1,200.00\n  Assets\n")]
+    fn can_parse_directive(#[case] r: ledger::Rule, #[case] directive: &str) {
+        assert!(parse(r, directive).len() > 0);
+    }
+
+    #[rstest]
+    #[case ("2023/01/05 Grocery Store\nExpenses:Food  $52.10\n")]             // unindented posting
+    #[case ("2023/01/05 Grocery Store\n\n2023/01/06 Landlord\n")]         // no postings
+    #[should_panic(expected = "expected note_line or posting")]
+    fn verify_xact_error(#[case] journal: &str) {
+        parse(ledger::Rule::ledger_journal, journal);
+    }
+
+    #[rstest]
+    #[case ("  Expenses:Food  $1,,0\n")]
+    #[case ("  Expenses:Food  $1,5\n")]             // digit groups have three digits
+    #[case ("  Expenses:Food  $1,0000.00\n")]
+    fn verify_quantity_error(#[case] posting: &str) {
+        assert!(ledger::Parser::parse(ledger::Rule::posting, posting).is_err());
+    }
+
+    #[test]
+    fn can_parse_journal() {
+        let journal = "; A ledger-cli journal
+# with comments of several kinds
+account Assets:Checking
+alias chk=Assets:Checking
+
+comment
+anything goes here
+end comment
+
+= /^Expenses:Food/
+    (Budget:Food)  -1
+
+~ Monthly
+    Expenses:Rent  $1,200.00
+    Assets:Checking
+
+apply account Personal
+2023/01/05 * Grocery Store
+    Expenses:Food  $52.10
+    chk
+end apply account
+";
+        assert!(parse(ledger::Rule::ledger_journal, journal).len() > 0);
+    }
+}
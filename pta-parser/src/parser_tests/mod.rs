@@ -12,6 +12,8 @@ pub use rstest::rstest;
 use super::parsers::*;
 
 mod basics;
+mod hledger_journal;
+mod ledger_cli;
 mod transaction;
 
 
@@ -25,11 +27,34 @@ mod directives {
     #[case (generic::Rule::directive_open,  "2001-09-11 open assets")]
     #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash")]
     #[case (generic::Rule::directive_open,  "2001-09-11 open Assets1:cash2:3petty")]
+    #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash USD")]
+    #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash USD,BTC , EUR")]
+    #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash USD \"STRICT\"")]
+    #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash \"STRICT_WITH_SIZE\"")]
+    #[case (generic::Rule::directive_open,  "2001-09-11 open assets:cash\t\"FIFO\"")]
     #[case (generic::Rule::directive_close, "2001-09-11 close assets")]
     #[case (generic::Rule::directive_close, "2001-09-11 close assets1:2cash:3petty")]
     #[case (generic::Rule::directive_commodity, "2001-09-11 commodity USD")]
     #[case (generic::Rule::balance_directive,   "2001-09-11 balance assets 123.456 USD")]
     #[case (generic::Rule::balance_directive,   "2001-09-11 balance assets1:2cash -0.456 USD")]
+    #[case (generic::Rule::directive_price, "2001-09-11 price BTC 400.00 USD")]
+    #[case (generic::Rule::directive_price, "2001-09-11 price\tEUR\t1.0932 USD")]
+    #[case (generic::Rule::directive_pad, "2001-09-11 pad assets:cash equity:opening")]
+    #[case (generic::Rule::directive_note, "2001-09-11 note assets:cash \"Called the bank\"")]
+    #[case (generic::Rule::directive_note, "2001-09-11 note assets:cash \"\"")]
+    #[case (generic::Rule::directive_document, "2001-09-11 document assets:cash \"statements/2001-09.pdf\"")]
+    #[case (generic::Rule::directive_document, "2001-09-11 document assets:cash\t\"/archive/2001 09.pdf\"")]
+    #[case (generic::Rule::directive_event, "2001-09-11 event \"location\" \"New York, USA\"")]
+    #[case (generic::Rule::directive_event, "2001-09-11 event \"employer\" \"\"")]
+    #[case (generic::Rule::directive_plugin, "plugin \"beancount.plugins.auto_accounts\"")]
+    #[case (generic::Rule::directive_plugin, "plugin \"beancount.plugins.check_commodity\"\t\"\"")]
+    #[case (generic::Rule::directive_pushtag, "pushtag #trip-2001")]
+    #[case (generic::Rule::directive_poptag, "poptag\t#trip-2001")]
+    #[case (generic::Rule::directive_include, "include \"accounts.beancount\"")]
+    #[case (generic::Rule::directive_include, "include \"../2001/*.beancount\"")]
+    #[case (generic::Rule::directive_option, "option \"title\" \"Family Ledger 2001\"")]
+    #[case (generic::Rule::directive_option, "option \"operating_currency\"\t\"USD\"")]
+    #[case (generic::Rule::directive_option, "option \"title\" \"\"")]
     fn can_parse_misc_directive(#[case] r: generic::Rule, #[case] base: &str) {
 
         // NOTE: addons must end in \n to match rules
@@ -50,6 +75,149 @@ mod directives {
         }
     }
 
+
+    #[rstest]
+    #[case ("2001-09-11 open assets \"MAGIC\"\n")]     // unknown booking method
+    #[case ("2001-09-11 open assets \"fifo\"\n")]
+    #[should_panic(expected = "expected booking_method")]
+    fn verify_open_booking_method_error(#[case] bad_open: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_open, bad_open)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_open);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("2001-09-11 open assets USD, \n")]         // dangling currency separator
+    #[case ("2001-09-11 open assets USD,,BTC\n")]
+    #[should_panic(expected = "expected currency")]
+    fn verify_open_currencies_error(#[case] bad_open: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_open, bad_open)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_open);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("option \"\n\"Family Ledger\"\n")]        // name split by newline
+    #[case ("option \"\" \"Family Ledger\"\n")]
+    #[should_panic(expected = "expected option_name")]
+    fn verify_option_error(#[case] bad_option: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_option, bad_option)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_option);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case (generic::Rule::directive_commodity, "1792-01-01 commodity USD\n  name: \"US Dollar\"\n  export: \"CASH\"\n")]
+    #[case (generic::Rule::directive_open, "2001-09-11 open assets:cash\n\topened: 2001-09-10\n\tlimit: -500 ; overdraft\n")]
+    #[case (generic::Rule::directive_open, "2001-09-11 open assets:cash\n  closing-acct: equity:closing\n  currency: USD\n  active: TRUE\n")]
+    #[case (generic::Rule::balance_directive, "2001-09-11 balance assets:cash 1.00 USD\n  verified: FALSE\n  statement: \"\"\n")]
+    #[case (generic::Rule::transaction_block, "2001-09-11 * \"x\"\n  invoice: \"2001-001\"\n  assets:cash    1.00 USD\n    note_1: \"change\"\n  equity\n")]
+    fn can_parse_metadata(#[case] r: generic::Rule, #[case] item: &str) {
+        let pairs = generic::Parser::parse(r, item)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        let lines = pairs.flatten().filter(|p| p.as_rule() == generic::Rule::metadata_line).count();
+        assert_eq!(lines, item.lines().filter(|l| l.contains(": ")).count());
+    }
+
+
+    #[rstest]
+    #[case ("2001-09-11 open assets:cash\n  Name: \"Cash\"\n")]          // key begins with upper case
+    #[case ("2001-09-11 open assets:cash\n  name:\"Cash\"\n")]
+    #[should_panic(expected = "expected EOI")]
+    fn verify_metadata_error(#[case] bad_metadata: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::generic_ledger, bad_metadata)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_metadata);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("2001-09-11 open assets:cash\n  name: \"Cash\n")]           // unterminated string
+    #[case ("2001-09-11 open assets:cash\n  name: @home\n")]
+    #[should_panic(expected = "expected iso8601_date_extended, currency, acct_descriptor, metadata_number, or metadata_bool")]
+    fn verify_metadata_value_error(#[case] bad_metadata: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::generic_ledger, bad_metadata)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_metadata);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("2001-09-11 document assets:cash \"\"\n")]       // empty path
+    #[case ("2001-09-11 document assets:cash \"\"\t; no path\n")]
+    #[should_panic(expected = "expected document_path")]
+    fn verify_document_error(#[case] bad_document: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_document, bad_document)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_document);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("pushtag trip-2001\n")]          // tag without '#'
+    #[case ("pushtag # trip-2001\n")]
+    #[should_panic(expected = "expected tag")]
+    fn verify_pushtag_error(#[case] bad_pushtag: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_pushtag, bad_pushtag)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_pushtag);
+        assert!(false);
+    }
+
+
+    #[rstest]
+    #[case ("2001-09-11 price BTC 400.00\n")]          // price without currency
+    #[case ("2001-09-11 price BTC 400.00USD\n")]
+    #[should_panic(expected = "expected amount")]
+    fn verify_price_error(#[case] bad_price: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::directive_price, bad_price)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_price);
+        assert!(false);
+    }
+
 }
 
 
@@ -73,6 +241,8 @@ mod ledger_file {
     ;; Misc
     1792-01-01 commodity USD ; US Dollar
     2001-09-11 commodity BTC ; Bitcoin launch date
+    2001-09-12 price BTC 400.00 USD
+    option \"operating_currency\" \"USD\"
     
     ")]
     fn can_parse_ledger(#[case] year: &str) {
@@ -89,6 +259,49 @@ mod ledger_file {
 
 
 
+#[cfg(test)]
+mod dialect {
+    use super::*;
+    use std::path::Path;
+
+    #[rstest]
+    #[case (Some("main.beancount"), "", Dialect::Beancount)]
+    #[case (Some("2001/01.bean"), "", Dialect::Beancount)]
+    #[case (Some("main.txt"), "2001-09-11 open assets\n", Dialect::Generic)]
+    #[case (Some("main.txt"), "option \"title\" \"Family\"\n2001-09-11 open assets:cash\n", Dialect::Generic)]
+    #[case (None, "; accounts\nplugin \"beancount.plugins.auto_accounts\"\n", Dialect::Beancount)]
+    #[case (None, "2001-09-11 open assets\n", Dialect::Generic)]
+    #[case (Some("main.ldg"), "", Dialect::Ledger)]
+    #[case (Some("main.ledger"), "", Dialect::Ledger)]
+    #[case (Some("main.dat"), "", Dialect::Ledger)]
+    #[case (Some("main.ledger"), "2001-09-11 open assets\n", Dialect::Ledger)]         // the extension wins
+    #[case (None, "2001/09/11 * Payee\n  Assets:Cash  $10\n  Equity\n", Dialect::Ledger)]
+    #[case (None, "account Assets:Cash\n", Dialect::Ledger)]
+    #[case (None, "= /Food/\n  (Budget:Food)  -1\n", Dialect::Ledger)]
+    #[case (None, "P 2023/01/05 AAPL $150.00\n2023/01/05 * Payee\n", Dialect::Ledger)]
+    #[case (None, "P 2023-01-05 AAPL $150.00\nD $1,000.00\nY 2023\n", Dialect::Ledger)]
+    #[case (None, "year 2023\n1/5 * Payee\n  Assets:Cash  $10 = $110\n  Equity\n", Dialect::Ledger)]
+    #[case (Some("2023.journal"), "", Dialect::Hledger)]
+    #[case (None, "decimal-mark ,\n", Dialect::Hledger)]
+    #[case (None, "P 2023/01/05 AAPL $150.00\n2023/01/05 * Payee\n  Assets:Cash  $10 == $110\n  Equity\n", Dialect::Hledger)]
+    #[case (None, "2023-01-05 * Payee\n  Assets:Cash  $10 =* $110\n  Equity\n", Dialect::Hledger)]
+    #[case (None, "account Assets:Cash  ; type: A\n", Dialect::Hledger)]
+    fn can_detect_dialect(#[case] file: Option<&str>, #[case] content: &str, #[case] expected: Dialect) {
+        assert_eq!(Dialect::detect(file.map(Path::new), content), expected);
+    }
+
+    #[rstest]
+    #[case (Dialect::Generic)]
+    #[case (Dialect::Beancount)]
+    #[case (Dialect::Ledger)]
+    #[case (Dialect::Hledger)]
+    fn can_round_trip_dialect_names(#[case] dialect: Dialect) {
+        assert_eq!(dialect.to_string().parse::<Dialect>(), Ok(dialect));
+        assert!("ledger-cli".parse::<Dialect>().is_err());
+    }
+}
+
+
 
 pub fn get_pairs(r: generic::Rule, content: &str) -> Pairs<'_, generic::Rule> {
     let x = generic::Parser::parse(
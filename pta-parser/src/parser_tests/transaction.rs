@@ -15,6 +15,17 @@ mod posting {
     #[rstest]
     #[case ("  Assets:subacct1    1.0000")]
     #[case ("\tEquity   \t -1.0000")]
+    #[case ("  Assets:Bank:AllyChk    -400.00 USD")]
+    #[case ("\tLiabilities:Fees\t0.00000005\tUSD")]
+    #[case ("  Assets:Exchanges:Coinbase    1.03683606 BTC { 381.9697397 USD, 2016-01-28 }")]   // per-unit cost
+    #[case ("  Assets:Exchanges:Coinbase    1.03683606 BTC {381.9697397 USD}")]
+    #[case ("  Assets:Exchanges:Coinbase    1.03683606 BTC {{396.04 USD, 2016-01-28, \"lot1\"}}")] // total cost
+    #[case ("  Assets:Exchanges:Coinbase    -1.03683606 BTC {}")]     // any lot
+    #[case ("  Assets:Exchanges:Coinbase    -1.03683606 BTC {\"lot1\"}")]
+    #[case ("  Assets:EUR    -100.00 EUR @ 1.08 USD")]                   // per-unit price
+    #[case ("  Assets:EUR    -100.00 EUR @@ 108.00 USD")]                // total price
+    #[case ("  Assets:Exchanges:Coinbase    -1.00 BTC {381.97 USD} @ 400.00 USD")]
+    #[case ("  Expenses:Food")]                                         // elided amount
     fn can_parse_posting_basic(#[case] base: &str) {
 
         // NOTE: addons must end in \n to match rules
@@ -52,6 +63,24 @@ mod posting {
         println!("Test case '{}' should fail to parse!", bad_date);
         assert!(false);
     }
+
+
+    #[rstest]
+    #[case ("  Assets:Bank    -400.00 usd\n")]    // currency must be upper case
+    #[case ("  Assets:Bank    -400.00 US\n")]
+    #[case ("  Assets:Bank    1.00 BTC {381.97 usd}\n")]
+    #[case ("  Assets:EUR    -100.00 EUR @ 1.08 usd\n")]
+    #[should_panic(expected = "expected currency")]
+    fn verify_posting_currency_error(#[case] bad_posting: &str) {
+
+        generic::Parser::parse(
+            generic::Rule::posting_basic, bad_posting)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        // should never reach this code since all cases should result in panic
+        println!("Test case '{}' should fail to parse!", bad_posting);
+        assert!(false);
+    }
 }
 
 
@@ -61,20 +90,32 @@ mod trans_block {
 
 
     // An example beancount transaction
-    // 2016-01-28 * " Buy BTC"     ; 10:01 am, xfer id 56aa57787199a73d29000650
-    //   Assets:Exchanges:Coinbase                     1.03683606 BTC { 381.9697397 USD, 2016-01-28 }
-    //   Assets:Bank:AllyChk                        -400.00 USD   ; verified w/register
-    //   Liabilities:Fees:Coinbase                     3.96 USD
-    //   Liabilities:Fees:Adjustment                   0.00000005 USD
-    
+    const BEANCOUNT_EXAMPLE: &str = "2016-01-28 * \" Buy BTC\"     ; 10:01 am, xfer id 56aa57787199a73d29000650
+  Assets:Exchanges:Coinbase                     1.03683606 BTC { 381.9697397 USD, 2016-01-28 }
+  Assets:Bank:AllyChk                        -400.00 USD   ; verified w/register
+  Liabilities:Fees:Coinbase                     3.96 USD
+  Liabilities:Fees:Adjustment                   0.00000005 USD
+";
+
+    #[test]
+    fn can_parse_beancount_example() {
+        generic::Parser::parse(generic::Rule::transaction_block, BEANCOUNT_EXAMPLE)
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        beancount::Parser::parse(beancount::Rule::transaction_block, BEANCOUNT_EXAMPLE)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
     #[rstest]
     // #[ignore = "wip"]
     #[case ("2009-01-09 ! \"Bitcoin launch date\" ;comment \n\tAssets    1.0000 ;posting comment\n\tEquity    -1.0000 \n")]
     #[case ("2009-01-09 ! \"Bitcoin launch date\"\n\tassets    1.0000\n  equity    -1.0000\n")]
+    #[case ("2009-01-09 ! \"Bitcoin launch date\" #genesis ^block-0 #btc/2009 ;comment\n\tassets    1.0000\n  equity    -1.0000\n")]
+    #[case ("2009-01-09 ! \"Bitcoin launch date\"\t^invoice_1.pdf\n\tassets    1.0000\n  equity    -1.0000\n")]
     fn can_parse_trans_block(#[case] tblock: &str) {
 
         let pairs = generic::Parser::parse(
-            generic::Rule::transaction_block, &tblock)
+            generic::Rule::transaction_block, tblock)
             .unwrap_or_else(|e| panic!("{}", e));
 
         // Parsing succeeded; ensure at least 1 pair was returned
@@ -85,10 +126,11 @@ mod trans_block {
     // #[ignore = "wip"]
     #[case ("2009-01-09 ! \"Bitcoin launch date\"
     ")]
-    #[should_panic(expected = "expected transaction_block")]
+    // NOTE: postings are parsed as part of the block, so the missing posting is reported
+    #[should_panic(expected = "expected posting_basic")]
     fn verify_trans_block_posting_error(#[case] bad_block: &str) {
         generic::Parser::parse(
-            generic::Rule::transaction_block, &bad_block)
+            generic::Rule::transaction_block, bad_block)
             .unwrap_or_else(|e| panic!("{}", e));
 
         // should never reach this code since all cases should result in panic
@@ -103,7 +145,7 @@ mod trans_block {
     #[should_panic(expected = "expected trans_header")]
     fn verify_trans_block_trans_header_error(#[case] bad_block: &str) {
         generic::Parser::parse(
-            generic::Rule::transaction_block, &bad_block)
+            generic::Rule::transaction_block, bad_block)
             .unwrap_or_else(|e| panic!("{}", e));
 
         // should never reach this code since all cases should result in panic
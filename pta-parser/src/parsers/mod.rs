@@ -5,8 +5,92 @@
 
 
 
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
 use pest_derive::*;
 
+
+// The plain text accounting dialects which can be parsed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Generic,
+    Beancount,
+    Ledger,
+    Hledger,
+}
+
+impl Dialect {
+    // The dialect of a ledger, from the extension of its file (if any), else from its content:
+    //  beancount's plugin directives mark a beancount ledger (options do not, as the generic
+    //  grammar has them too), what only hledger reads (decimal-mark directives, == and =* balance
+    //  assertions and account type tags) an hledger one, while the other directives of journals,
+    //  automated and periodic transactions and YYYY/MM/DD dates mark a ledger-cli one
+    pub fn detect(file: Option<&Path>, content: &str) -> Dialect {
+        match file.and_then(|f| f.extension()).and_then(|e| e.to_str()) {
+            Some("beancount") | Some("bean") => { return Dialect::Beancount; }
+            Some("ledger") | Some("ldg") | Some("dat") => { return Dialect::Ledger; }
+            Some("journal") | Some("hledger") => { return Dialect::Hledger; }
+            _ => {}
+        }
+
+        let beancount = content.lines().any(|l| l.starts_with("plugin \""));
+        if beancount {
+            return Dialect::Beancount;
+        }
+
+        fn keyword(line: &str) -> &str {
+            return line.split_whitespace().next().unwrap_or_default();
+        }
+
+        let hledger = content.lines().any(|l| {
+            let posting = l.starts_with([' ', '\t']);
+            let note = l.split_once(';').map(|(_, note)| note).unwrap_or_default();
+            return keyword(l) == "decimal-mark"
+                || (posting && (l.contains("==") || l.contains("=*")))
+                || (keyword(l) == "account" && note.contains("type:"));
+        });
+        if hledger {
+            return Dialect::Hledger;
+        }
+
+        let ledger = content.lines().any(|l| {
+            let slash_date = l.len() >= 5 && l.as_bytes()[..4].iter().all(u8::is_ascii_digit) && l.as_bytes()[4] == b'/';
+            return slash_date
+                || matches!(keyword(l), "account" | "alias" | "apply" | "payee" | "P" | "D" | "Y" | "year" | "=" | "~");
+        });
+        return if ledger { Dialect::Ledger } else { Dialect::Generic };
+    }
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "generic" => { return Ok(Dialect::Generic); }
+            "beancount" => { return Ok(Dialect::Beancount); }
+            "ledger" => { return Ok(Dialect::Ledger); }
+            "hledger" => { return Ok(Dialect::Hledger); }
+            _ => { return Err(format!("unknown dialect {s}")); }
+        }
+    }
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Dialect::Generic => "generic",
+            Dialect::Beancount => "beancount",
+            Dialect::Ledger => "ledger",
+            Dialect::Hledger => "hledger",
+        };
+        write!(f, "{s}")
+    }
+}
+
 pub mod generic {
 
     use super::*;
@@ -27,4 +111,156 @@ pub mod beancount {
     #[grammar = "./grammars/beancount.pest"]
     pub struct Parser;
 
-}
\ No newline at end of file
+    // Beancount's grammar is the generic grammar with stricter account names, so each of its rules
+    //  is the generic rule of the same name and a beancount parse is handled as a generic one. The
+    //  match is exhaustive, so a rule added to the shared grammar must be mapped here.
+    impl From<Rule> for generic::Rule {
+        fn from(rule: Rule) -> generic::Rule {
+            return match rule {
+                Rule::beancount_ledger => generic::Rule::generic_ledger,
+                Rule::EOI => generic::Rule::EOI,
+                Rule::WHITESPACE => generic::Rule::WHITESPACE,
+                Rule::acct_descriptor => generic::Rule::acct_descriptor,
+                Rule::acct_separator => generic::Rule::acct_separator,
+                Rule::amount => generic::Rule::amount,
+                Rule::balance_directive => generic::Rule::balance_directive,
+                Rule::booking_method => generic::Rule::booking_method,
+                Rule::comment => generic::Rule::comment,
+                Rule::comment_or_newline => generic::Rule::comment_or_newline,
+                Rule::comment_text => generic::Rule::comment_text,
+                Rule::comment_token => generic::Rule::comment_token,
+                Rule::cost_component => generic::Rule::cost_component,
+                Rule::cost_components => generic::Rule::cost_components,
+                Rule::cost_label => generic::Rule::cost_label,
+                Rule::cost_per_unit => generic::Rule::cost_per_unit,
+                Rule::cost_spec => generic::Rule::cost_spec,
+                Rule::cost_total => generic::Rule::cost_total,
+                Rule::currency => generic::Rule::currency,
+                Rule::decimal_value => generic::Rule::decimal_value,
+                Rule::directive_close => generic::Rule::directive_close,
+                Rule::directive_commodity => generic::Rule::directive_commodity,
+                Rule::directive_document => generic::Rule::directive_document,
+                Rule::directive_event => generic::Rule::directive_event,
+                Rule::directive_include => generic::Rule::directive_include,
+                Rule::directive_note => generic::Rule::directive_note,
+                Rule::directive_open => generic::Rule::directive_open,
+                Rule::directive_option => generic::Rule::directive_option,
+                Rule::directive_pad => generic::Rule::directive_pad,
+                Rule::directive_plugin => generic::Rule::directive_plugin,
+                Rule::directive_poptag => generic::Rule::directive_poptag,
+                Rule::directive_price => generic::Rule::directive_price,
+                Rule::directive_pushtag => generic::Rule::directive_pushtag,
+                Rule::directives => generic::Rule::directives,
+                Rule::document_path => generic::Rule::document_path,
+                Rule::empty_line => generic::Rule::empty_line,
+                Rule::event_name => generic::Rule::event_name,
+                Rule::event_value => generic::Rule::event_value,
+                Rule::include_path => generic::Rule::include_path,
+                Rule::iso8601_date_extended => generic::Rule::iso8601_date_extended,
+                Rule::link => generic::Rule::link,
+                Rule::link_name => generic::Rule::link_name,
+                Rule::metadata_bool => generic::Rule::metadata_bool,
+                Rule::metadata_end => generic::Rule::metadata_end,
+                Rule::metadata_key => generic::Rule::metadata_key,
+                Rule::metadata_line => generic::Rule::metadata_line,
+                Rule::metadata_number => generic::Rule::metadata_number,
+                Rule::metadata_string => generic::Rule::metadata_string,
+                Rule::metadata_value => generic::Rule::metadata_value,
+                Rule::note_text => generic::Rule::note_text,
+                Rule::open_currencies => generic::Rule::open_currencies,
+                Rule::option_name => generic::Rule::option_name,
+                Rule::option_value => generic::Rule::option_value,
+                Rule::plugin_config => generic::Rule::plugin_config,
+                Rule::plugin_name => generic::Rule::plugin_name,
+                Rule::posting_basic => generic::Rule::posting_basic,
+                Rule::posting_indent => generic::Rule::posting_indent,
+                Rule::price_annotation => generic::Rule::price_annotation,
+                Rule::price_per_unit => generic::Rule::price_per_unit,
+                Rule::price_total => generic::Rule::price_total,
+                Rule::sub_acct => generic::Rule::sub_acct,
+                Rule::tag => generic::Rule::tag,
+                Rule::tag_name => generic::Rule::tag_name,
+                Rule::top_level_acct => generic::Rule::top_level_acct,
+                Rule::trans_annotation => generic::Rule::trans_annotation,
+                Rule::trans_description => generic::Rule::trans_description,
+                Rule::trans_description_text => generic::Rule::trans_description_text,
+                Rule::trans_header => generic::Rule::trans_header,
+                Rule::transaction_block => generic::Rule::transaction_block,
+            };
+        }
+    }
+
+}
+
+
+pub mod ledger {
+
+    use super::*;
+
+    #[derive(Parser)]
+    #[grammar = "./grammars/base.pest"]
+    #[grammar = "./grammars/journal.pest"]
+    #[grammar = "./grammars/ledger.pest"]
+    pub struct Parser;
+
+}
+
+
+pub mod hledger {
+
+    use super::*;
+
+    #[derive(Parser)]
+    #[grammar = "./grammars/base.pest"]
+    #[grammar = "./grammars/journal.pest"]
+    #[grammar = "./grammars/hledger.pest"]
+    pub struct Parser;
+
+}
+
+
+// The rules which the journal grammars of ledger-cli and hledger have alike (cf. journal.pest), by
+//  which the journals of either are built; a rule of a dialect's own is not one of them
+macro_rules! journal_rules {
+    ($($rule:ident),* $(,)?) => {
+        pub mod journal {
+            #[allow(non_camel_case_types)]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+            pub enum Rule {
+                $($rule),*
+            }
+        }
+
+        impl TryFrom<ledger::Rule> for journal::Rule {
+            type Error = ledger::Rule;
+
+            fn try_from(rule: ledger::Rule) -> Result<journal::Rule, ledger::Rule> {
+                return match rule {
+                    $(ledger::Rule::$rule => Ok(journal::Rule::$rule),)*
+                    _ => Err(rule),
+                };
+            }
+        }
+
+        impl TryFrom<hledger::Rule> for journal::Rule {
+            type Error = hledger::Rule;
+
+            fn try_from(rule: hledger::Rule) -> Result<journal::Rule, hledger::Rule> {
+                return match rule {
+                    $(hledger::Rule::$rule => Ok(journal::Rule::$rule),)*
+                    _ => Err(rule),
+                };
+            }
+        }
+    };
+}
+
+journal_rules!(
+    account_directive, account_name, alias_directive, alias_name, amount, apply_account,
+    auto_posting, auto_predicate, auto_xact, aux_date, balanced_virtual_account, code, commodity,
+    commodity_directive, date, default_commodity, default_year, directive, end_aliases,
+    end_apply_account, lot_date, lot_per_unit, lot_total, negative, note_line, note_text, payee,
+    payee_directive, payee_name, period, periodic_xact, posting, price_directive, price_per_unit,
+    price_total, quantity, state, sub_directive, sub_directive_name, sub_directive_value, time,
+    virtual_account, xact, xact_header, year,
+);
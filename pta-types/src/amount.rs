@@ -0,0 +1,411 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+
+// An exact decimal number; the value is mantissa * 10^-scale. The scale written in the
+//  ledger is preserved (e.g., "1.50" has scale 2), while equality & ordering are by value.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError {
+    pub input: String,
+}
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal value '{}'", self.input)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+
+fn pow10(exp: u32) -> Option<i128> {
+    return 10i128.checked_pow(exp);
+}
+
+// Integer division rounding half away from zero
+fn div_round(num: i128, den: i128) -> Option<i128> {
+    let q = num.checked_div(den)?;
+    let r = num.checked_rem(den)?;
+
+    if r.unsigned_abs().checked_mul(2)? >= den.unsigned_abs() {
+        return if (num < 0) == (den < 0) { q.checked_add(1) } else { q.checked_sub(1) };
+    }
+
+    return Some(q);
+}
+
+impl Decimal {
+    pub fn new(mantissa: i128, scale: u32) -> Decimal {
+        return Decimal { mantissa, scale };
+    }
+
+    pub fn zero() -> Decimal {
+        return Decimal::default();
+    }
+
+    pub fn mantissa(&self) -> i128 {
+        return self.mantissa;
+    }
+
+    pub fn scale(&self) -> u32 {
+        return self.scale;
+    }
+
+    pub fn is_zero(&self) -> bool {
+        return self.mantissa == 0;
+    }
+
+    pub fn is_negative(&self) -> bool {
+        return self.mantissa < 0;
+    }
+
+    pub fn checked_abs(&self) -> Option<Decimal> {
+        return Some(Decimal { mantissa: self.mantissa.checked_abs()?, scale: self.scale });
+    }
+
+    // The same value expressed with a larger scale; None if the scale would shrink or on overflow
+    pub fn rescale(&self, scale: u32) -> Option<Decimal> {
+        let mantissa = self.mantissa.checked_mul(pow10(scale.checked_sub(self.scale)?)?)?;
+        return Some(Decimal { mantissa, scale });
+    }
+
+    // The value rounded (half away from zero) or extended to the given scale
+    pub fn round_dp(&self, scale: u32) -> Option<Decimal> {
+        if scale >= self.scale {
+            return self.rescale(scale);
+        }
+
+        let mantissa = div_round(self.mantissa, pow10(self.scale - scale)?)?;
+        return Some(Decimal { mantissa, scale });
+    }
+
+    // The same value without trailing fractional zeros
+    pub fn normalize(&self) -> Decimal {
+        let mut d = *self;
+        while d.scale > 0 && d.mantissa % 10 == 0 {
+            d.mantissa /= 10;
+            d.scale -= 1;
+        }
+
+        return d;
+    }
+
+    // The integer part and the fractional digits, without trailing zeros, of the magnitude
+    fn magnitude_parts(&self) -> (u128, String) {
+        let digits = format!("{:0>width$}", self.mantissa.unsigned_abs(), width = self.scale as usize + 1);
+        let (whole, frac) = digits.split_at(digits.len() - self.scale as usize);
+        return (whole.parse().unwrap_or_default(), frac.trim_end_matches('0').to_string());
+    }
+
+    fn aligned(&self, other: &Decimal) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        return Some((self.rescale(scale)?.mantissa, other.rescale(scale)?.mantissa, scale));
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        return Some(Decimal { mantissa: a.checked_add(b)?, scale });
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other)?;
+        return Some(Decimal { mantissa: a.checked_sub(b)?, scale });
+    }
+
+    pub fn checked_neg(&self) -> Option<Decimal> {
+        return Some(Decimal { mantissa: self.mantissa.checked_neg()?, scale: self.scale });
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        return Some(Decimal {
+            mantissa: self.mantissa.checked_mul(other.mantissa)?,
+            scale: self.scale.checked_add(other.scale)?,
+        });
+    }
+
+    // The quotient rounded (half away from zero) to the given scale; None when dividing by zero
+    pub fn checked_div(&self, other: &Decimal, scale: u32) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+
+        // self/other * 10^scale == self.mantissa * 10^(scale + other.scale - self.scale) / other.mantissa
+        let exp = scale as i64 + other.scale as i64 - self.scale as i64;
+        let (num, den) = if exp >= 0 {
+            (self.mantissa.checked_mul(pow10(exp as u32)?)?, other.mantissa)
+        } else {
+            (self.mantissa, other.mantissa.checked_mul(pow10((-exp) as u32)?)?)
+        };
+
+        return Some(Decimal { mantissa: div_round(num, den)?, scale });
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseDecimalError { input: s.to_string() };
+
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+
+        let (whole, frac) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if whole.is_empty() || !whole.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+            return Err(err());
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in whole.chars().chain(frac.chars()) {
+            mantissa = mantissa.checked_mul(10)
+                .and_then(|m| m.checked_add(c as i128 - '0' as i128))
+                .ok_or_else(err)?;
+        }
+
+        return Ok(Decimal {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale: frac.len() as u32,
+        });
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = format!("{:0>width$}", self.mantissa.unsigned_abs(), width = self.scale as usize + 1);
+        let (whole, frac) = digits.split_at(digits.len() - self.scale as usize);
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+
+        if frac.is_empty() {
+            return write!(f, "{sign}{whole}");
+        }
+
+        return write!(f, "{sign}{whole}.{frac}");
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other) == Ordering::Equal;
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.normalize().aligned(&other.normalize()) {
+            Some((a, b, _)) => { return a.cmp(&b); }
+
+            // when aligning the scales overflows, compare the signs, then the integer parts, then
+            //  the fractional digits (lexically, which orders them as numbers)
+            None => {
+                match (self.is_negative(), other.is_negative()) {
+                    (false, true) => { return Ordering::Greater; }
+                    (true, false) => { return Ordering::Less; }
+                    _ => {}
+                }
+
+                let magnitude = self.magnitude_parts().cmp(&other.magnitude_parts());
+                return if self.is_negative() { magnitude.reverse() } else { magnitude };
+            }
+        }
+    }
+}
+
+impl Hash for Decimal {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let d = self.normalize();
+        d.mantissa.hash(state);
+        d.scale.hash(state);
+    }
+}
+
+
+
+// A quantity of a commodity, e.g., "-400.00 USD". The commodity is empty when the
+//  ledger does not specify one.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Amount {
+    pub number: Decimal,
+    pub commodity: String,
+}
+
+impl Amount {
+    pub fn new(number: Decimal, commodity: &str) -> Amount {
+        return Amount { number, commodity: commodity.to_string() };
+    }
+
+    pub fn is_zero(&self) -> bool {
+        return self.number.is_zero();
+    }
+
+    // Sum of two amounts of the same commodity; None if the commodities differ or on overflow
+    pub fn checked_add(&self, other: &Amount) -> Option<Amount> {
+        if self.commodity != other.commodity {
+            return None;
+        }
+
+        return Some(Amount::new(self.number.checked_add(&other.number)?, &self.commodity));
+    }
+
+    // Difference of two amounts of the same commodity; None if the commodities differ or on overflow
+    pub fn checked_sub(&self, other: &Amount) -> Option<Amount> {
+        if self.commodity != other.commodity {
+            return None;
+        }
+
+        return Some(Amount::new(self.number.checked_sub(&other.number)?, &self.commodity));
+    }
+
+    pub fn checked_neg(&self) -> Option<Amount> {
+        return Some(Amount::new(self.number.checked_neg()?, &self.commodity));
+    }
+
+    // The amount scaled by a factor, keeping its commodity
+    pub fn checked_mul(&self, factor: &Decimal) -> Option<Amount> {
+        return Some(Amount::new(self.number.checked_mul(factor)?, &self.commodity));
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.commodity.is_empty() {
+            return write!(f, "{}", self.number);
+        }
+
+        return write!(f, "{} {}", self.number, self.commodity);
+    }
+}
+
+
+
+#[cfg(test)]
+mod amount_tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn dec(s: &str) -> Decimal {
+        return s.parse::<Decimal>().unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    #[rstest]
+    #[case ("1234.5678", 12345678, 4)]
+    #[case ("0.00000005", 5, 8)]
+    #[case ("-0.456", -456, 3)]
+    #[case ("0.00000000", 0, 8)]
+    #[case ("123", 123, 0)]
+    #[case ("+1.50", 150, 2)]
+    fn can_parse_decimal(#[case] s: &str, #[case] mantissa: i128, #[case] scale: u32) {
+        let d = dec(s);
+        assert_eq!(d.mantissa(), mantissa);
+        assert_eq!(d.scale(), scale);
+    }
+
+    #[rstest]
+    #[case ("")]
+    #[case ("-")]
+    #[case (".12")]
+    #[case ("1.2.3")]
+    #[case ("1,000.00")]
+    #[case ("12a")]
+    #[case ("99999999999999999999999999999999999999999.0")]
+    fn verify_decimal_parse_error(#[case] s: &str) {
+        assert!(s.parse::<Decimal>().is_err());
+    }
+
+    #[rstest]
+    #[case ("1234.5678")]
+    #[case ("0.00000005")]
+    #[case ("-0.456")]
+    #[case ("0.00000000")]
+    #[case ("-400.00")]
+    #[case ("42")]
+    fn display_preserves_scale(#[case] s: &str) {
+        assert_eq!(dec(s).to_string(), s);
+    }
+
+    #[rstest]
+    #[case ("1234.5678", "-1234.5678", "0.0000")]
+    #[case ("0.1", "0.2", "0.3")]
+    #[case ("3.96", "0.00000005", "3.96000005")]
+    #[case ("-400.00", "381.9697397", "-18.0302603")]
+    fn can_add_exactly(#[case] a: &str, #[case] b: &str, #[case] sum: &str) {
+        assert_eq!(dec(a).checked_add(&dec(b)).unwrap().to_string(), sum);
+    }
+
+    #[test]
+    fn can_sub_neg_mul_div() {
+        assert_eq!(dec("1.00").checked_sub(&dec("0.005")).unwrap().to_string(), "0.995");
+        assert_eq!(dec("1.50").checked_neg().unwrap().to_string(), "-1.50");
+        assert_eq!(dec("1.03683606").checked_mul(&dec("381.9697397")).unwrap(), dec("396.039999949773582"));
+        assert_eq!(dec("100.00").checked_div(&dec("3"), 4).unwrap().to_string(), "33.3333");
+        assert_eq!(dec("-2").checked_div(&dec("3"), 2).unwrap().to_string(), "-0.67");
+        assert!(dec("1").checked_div(&dec("0.00"), 2).is_none());
+        assert!(Decimal::new(i128::MAX, 0).checked_add(&dec("1")).is_none());
+    }
+
+    #[test]
+    fn can_compare_by_value() {
+        assert_eq!(dec("1.0"), dec("1.000"));
+        assert!(dec("-0.5") < dec("0.25"));
+        assert!(dec("10") > dec("9.99999"));
+        assert_eq!(dec("2.50").round_dp(1).unwrap().to_string(), "2.5");
+        assert_eq!(dec("2.45").round_dp(1).unwrap().to_string(), "2.5");
+        assert_eq!(dec("-2.45").round_dp(1).unwrap().to_string(), "-2.5");
+    }
+
+    #[test]
+    fn can_compare_beyond_aligned_scales() {
+        // aligning these scales overflows i128, so they are compared digit by digit
+        let big = Decimal::new(i128::MAX, 0);
+        let tiny = Decimal::new(1, 38);
+        let tinier = Decimal::new(2, 38).checked_div(&dec("3"), 38).unwrap();
+        assert!(big > tiny);
+        assert!(big.checked_neg().unwrap() < tiny.checked_neg().unwrap());
+        assert!(Decimal::new(i128::MAX - 1, 0) < big);
+        assert_ne!(Decimal::new(10i128.pow(37) + 1, 37), Decimal::new(1, 0));
+        assert!(Decimal::new(10i128.pow(37) + 1, 37) > Decimal::new(1, 0).checked_add(&tinier).unwrap());
+    }
+
+    #[test]
+    fn can_take_checked_abs() {
+        assert_eq!(dec("-1.50").checked_abs().unwrap().to_string(), "1.50");
+        assert_eq!(dec("2").checked_abs().unwrap(), dec("2"));
+        assert!(Decimal::new(i128::MIN, 2).checked_abs().is_none());
+    }
+
+    #[test]
+    fn can_do_amount_arithmetic() {
+        let usd = Amount::new(dec("-400.00"), "USD");
+        let fee = Amount::new(dec("3.96"), "USD");
+        let btc = Amount::new(dec("1.03683606"), "BTC");
+
+        assert_eq!(usd.checked_add(&fee).unwrap().to_string(), "-396.04 USD");
+        assert_eq!(usd.checked_sub(&fee).unwrap().to_string(), "-403.96 USD");
+        assert_eq!(usd.checked_neg().unwrap().to_string(), "400.00 USD");
+        assert_eq!(fee.checked_mul(&dec("2")).unwrap().to_string(), "7.92 USD");
+        assert!(usd.checked_add(&btc).is_none());
+        assert!(usd.checked_sub(&btc).is_none());
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::collections::BTreeMap;
+
+use log::warn;
+
+use super::*;
+
+
+// The options of a ledger, set by option "Name" "Value" directives. Names follow beancount's.
+#[derive(Debug, Clone)]
+pub struct LedgerOptions {
+    pub title: String,
+    // The currencies in which reports are presented; each option adds one
+    pub operating_currency: Vec<String>,
+    pub name_assets: String,
+    pub name_liabilities: String,
+    pub name_equity: String,
+    pub name_income: String,
+    pub name_expenses: String,
+    // Tolerances for amounts without decimal places, keyed by currency or "*" for any,
+    //  each set by an option of the form "CUR:0.005"
+    pub inferred_tolerance_default: BTreeMap<String, Decimal>,
+    pub inferred_tolerance_multiplier: Decimal,
+    // The booking method of accounts whose open directive names none
+    pub booking_method: BookingMethod,
+}
+
+impl Default for LedgerOptions {
+    fn default() -> Self {
+        return LedgerOptions {
+            title: String::new(),
+            operating_currency: Vec::new(),
+            name_assets: "Assets".to_string(),
+            name_liabilities: "Liabilities".to_string(),
+            name_equity: "Equity".to_string(),
+            name_income: "Income".to_string(),
+            name_expenses: "Expenses".to_string(),
+            inferred_tolerance_default: BTreeMap::new(),
+            inferred_tolerance_multiplier: Decimal::new(5, 1),
+            booking_method: BookingMethod::default(),
+        };
+    }
+}
+
+impl LedgerOptions {
+    // Sets the named option from its (unquoted) value; unknown options, such as beancount's
+    //  rendering options, are ignored with a warning
+    pub fn set(self: &mut Self, name: &str, value: &str) -> Result<(), String> {
+        let decimal = |v: &str| v.trim().parse::<Decimal>().map_err(|e| format!("invalid value for option {name}: {e}"));
+
+        match name {
+            "title" => { self.title = value.to_string(); }
+            "operating_currency" => { self.operating_currency.push(value.trim().to_string()); }
+            "name_assets" => { self.name_assets = value.trim().to_string(); }
+            "name_liabilities" => { self.name_liabilities = value.trim().to_string(); }
+            "name_equity" => { self.name_equity = value.trim().to_string(); }
+            "name_income" => { self.name_income = value.trim().to_string(); }
+            "name_expenses" => { self.name_expenses = value.trim().to_string(); }
+            "inferred_tolerance_default" => {
+                let (currency, tolerance) = value.split_once(':')
+                    .ok_or_else(|| format!("invalid value for option {name}: expected CURRENCY:TOLERANCE, found '{value}'"))?;
+                self.inferred_tolerance_default.insert(currency.trim().to_string(), decimal(tolerance)?);
+            }
+            "inferred_tolerance_multiplier" => { self.inferred_tolerance_multiplier = decimal(value)?; }
+            "booking_method" => { self.booking_method = value.trim().parse()?; }
+            _ => { warn!("ignoring unknown option {name}"); }
+        }
+
+        return Ok(());
+    }
+
+    // The names of the root accounts, in the order assets, liabilities, equity, income, expenses
+    pub fn root_accounts(self: &Self) -> [&str; 5] {
+        return [&self.name_assets, &self.name_liabilities, &self.name_equity, &self.name_income, &self.name_expenses];
+    }
+}
+
+
+
+#[cfg(test)]
+mod ledger_options_tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn can_set_options() {
+        let mut options = LedgerOptions::default();
+        let settings = [
+            ("title", "Family Ledger"),
+            ("operating_currency", "USD"),
+            ("operating_currency", "EUR"),
+            ("name_assets", "Actifs"),
+            ("inferred_tolerance_default", "USD:0.005"),
+            ("inferred_tolerance_default", "*:0.5"),
+            ("inferred_tolerance_multiplier", "1.1"),
+            ("booking_method", "FIFO"),
+        ];
+        for (name, value) in settings {
+            options.set(name, value).unwrap_or_else(|e| panic!("{}", e));
+        }
+
+        assert_eq!(options.title, "Family Ledger");
+        assert_eq!(options.operating_currency, vec!["USD", "EUR"]);
+        assert_eq!(options.root_accounts(), ["Actifs", "Liabilities", "Equity", "Income", "Expenses"]);
+        assert_eq!(options.inferred_tolerance_default.get("USD"), Some(&Decimal::new(5, 3)));
+        assert_eq!(options.inferred_tolerance_default.get("*"), Some(&Decimal::new(5, 1)));
+        assert_eq!(options.inferred_tolerance_multiplier, Decimal::new(11, 1));
+        assert_eq!(options.booking_method, BookingMethod::Fifo);
+    }
+
+    #[test]
+    fn can_ignore_unknown_option() {
+        let mut options = LedgerOptions::default();
+        options.set("render_commas", "TRUE").unwrap_or_else(|e| panic!("{}", e));
+        options.set("operating_currencies", "USD").unwrap_or_else(|e| panic!("{}", e));
+        assert!(options.operating_currency.is_empty());
+    }
+
+    #[rstest]
+    #[case ("inferred_tolerance_default", "0.005", "expected CURRENCY:TOLERANCE")]
+    #[case ("inferred_tolerance_default", "USD:abc", "invalid value")]
+    #[case ("inferred_tolerance_multiplier", "", "invalid value")]
+    #[case ("booking_method", "fifo", "fifo")]
+    fn verify_option_error(#[case] name: &str, #[case] value: &str, #[case] msg: &str) {
+        let err = LedgerOptions::default().set(name, value).expect_err("option should be rejected");
+        assert!(err.contains(msg), "missing '{}' in '{}'", msg, err);
+    }
+}
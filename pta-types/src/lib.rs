@@ -3,20 +3,54 @@
 // This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
 //
 
-#[derive(Default, Clone)]
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct FilePosition {
     pub line: usize,
-    pub col: usize        // TODO: u16? u32 is probably overkill
+    pub col: usize,       // TODO: u16? u32 is probably overkill
+    // The byte offset from the start of the source
+    pub offset: usize,
 }
 
-#[derive(Default, Clone)]
+// Identifies the string or file an item was parsed from, as registered with the ParsedLedger.
+//  The default, 0, identifies no source (e.g., items created rather than parsed).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SourceId(pub usize);
+
+#[derive(Debug, Default, Clone)]
 pub struct ParserInfo {
     pub position: FilePosition,
+    // The position just past the end of the item's text
+    pub end: FilePosition,
+    pub source: SourceId,
+    // The file the item was parsed from, shared by all of its items; None when parsed from a string
+    pub file: Option<Arc<Path>>,
+}
+
+impl ParserInfo {
+    // The item's text within its source
+    pub fn text<'a>(&self, source: &'a str) -> Option<&'a str> {
+        return source.get(self.position.offset..self.end.offset);
+    }
 }
 
 
+pub mod amount;
+pub use amount::*;
+
+pub mod ledger_options;
+pub use ledger_options::*;
+
+pub mod metadata;
+pub use metadata::*;
+
 pub mod parsed_ledger;
 pub use parsed_ledger::*;
 
+pub mod raw_directive;
+pub use raw_directive::*;
+
 pub mod raw_transaction;
-pub use raw_transaction::*;
\ No newline at end of file
+pub use raw_transaction::*;
@@ -0,0 +1,116 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::fmt;
+
+use super::*;
+
+
+// The value of a metadata line, typed by its syntax: "quoted" strings, numbers, YYYY-MM-DD dates,
+//  accounts, currencies and TRUE/FALSE
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetaValue {
+    String(String),
+    Number(Decimal),
+    Date(String),
+    Account(String),
+    Currency(String),
+    Bool(bool),
+}
+
+impl fmt::Display for MetaValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetaValue::String(s) => write!(f, "\"{}\"", s),
+            MetaValue::Number(n) => write!(f, "{}", n),
+            MetaValue::Date(d) => write!(f, "{}", d),
+            MetaValue::Account(a) => write!(f, "{}", a),
+            MetaValue::Currency(c) => write!(f, "{}", c),
+            MetaValue::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+        }
+    }
+}
+
+// The metadata of a transaction, posting or directive, by key; entries are kept in the order
+//  they appear in the source
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    entries: Vec<(String, MetaValue)>,
+}
+
+impl Metadata {
+    // Sets the value of the key, in place if it is already present, returning its previous value
+    pub fn insert(self: &mut Self, key: String, value: MetaValue) -> Option<MetaValue> {
+        match self.entries.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => { return Some(std::mem::replace(v, value)); }
+            None => {
+                self.entries.push((key, value));
+                return None;
+            }
+        }
+    }
+
+    pub fn get(self: &Self, key: &str) -> Option<&MetaValue> {
+        return self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    }
+
+    pub fn contains_key(self: &Self, key: &str) -> bool {
+        return self.get(key).is_some();
+    }
+
+    pub fn iter(self: &Self) -> impl Iterator<Item = (&String, &MetaValue)> {
+        return self.entries.iter().map(|(k, v)| (k, v));
+    }
+
+    pub fn len(self: &Self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        return self.entries.is_empty();
+    }
+}
+
+impl Extend<(String, MetaValue)> for Metadata {
+    fn extend<I: IntoIterator<Item = (String, MetaValue)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod metadata_tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case (MetaValue::String("US Dollar".to_string()), "\"US Dollar\"")]
+    #[case (MetaValue::Number(Decimal::new(-150, 2)), "-1.50")]
+    #[case (MetaValue::Date("2001-09-11".to_string()), "2001-09-11")]
+    #[case (MetaValue::Account("Assets:Cash".to_string()), "Assets:Cash")]
+    #[case (MetaValue::Currency("USD".to_string()), "USD")]
+    #[case (MetaValue::Bool(false), "FALSE")]
+    fn can_display_meta_value(#[case] value: MetaValue, #[case] expected: &str) {
+        assert_eq!(value.to_string(), expected);
+    }
+
+    #[test]
+    fn can_keep_metadata_in_source_order() {
+        let mut meta = Metadata::default();
+        assert_eq!(meta.insert("opened".to_string(), MetaValue::Date("2001-09-11".to_string())), None);
+        assert_eq!(meta.insert("limit".to_string(), MetaValue::Number(Decimal::new(-500, 0))), None);
+        assert_eq!(meta.insert("active".to_string(), MetaValue::Bool(true)), None);
+        assert_eq!(meta.insert("limit".to_string(), MetaValue::Number(Decimal::new(-100, 0))),
+            Some(MetaValue::Number(Decimal::new(-500, 0))));
+
+        assert_eq!(meta.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>(), vec!["opened", "limit", "active"]);
+        assert_eq!(meta.get("limit"), Some(&MetaValue::Number(Decimal::new(-100, 0))));
+        assert!(meta.contains_key("active") && !meta.contains_key("closing"));
+        assert_eq!(meta.len(), 3);
+    }
+}
@@ -3,17 +3,440 @@
 // This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
 //
 
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+
 use super::*;
 use raw_transaction;
 
 
-#[derive(Default)]
+#[derive(Debug, Default)]
 pub struct ParsedLedger {
     xns: Vec<raw_transaction::RawTransaction>,
+    directives: Vec<raw_directive::RawDirective>,
+    // Each source, indexed by SourceId - 1
+    sources: Vec<Source>,
+    options: LedgerOptions,
+    plugins: Vec<raw_directive::RawPlugin>,
+}
+
+// A string or file from which items are parsed
+#[derive(Debug, Default)]
+struct Source {
+    file: Option<Arc<Path>>,
+    // The source and byte offset of the include which named the file; None for the root
+    included_at: Option<(SourceId, usize)>,
+}
+
+// An item of the ledger, as found by source line
+#[derive(Debug, Clone, Copy)]
+pub enum LedgerItem<'a> {
+    Transaction(&'a raw_transaction::RawTransaction),
+    Directive(&'a raw_directive::RawDirective),
+}
+
+// A transaction or directive, owned; the unit on which plugins work
+#[derive(Debug, Clone)]
+pub enum LedgerEntry {
+    Transaction(raw_transaction::RawTransaction),
+    Directive(raw_directive::RawDirective),
+}
+
+impl LedgerEntry {
+    pub fn date(self: &Self) -> &str {
+        match self {
+            LedgerEntry::Transaction(xn) => { return &xn.date; }
+            LedgerEntry::Directive(d) => { return d.date(); }
+        }
+    }
+
+    pub fn pinfo(self: &Self) -> &ParserInfo {
+        match self {
+            LedgerEntry::Transaction(xn) => { return &xn.pinfo; }
+            LedgerEntry::Directive(d) => { return d.pinfo(); }
+        }
+    }
 }
 
 impl ParsedLedger {
     pub fn add_transaction(self: &mut Self, xn: raw_transaction::RawTransaction) {
         self.xns.push(xn);
     }
-}
\ No newline at end of file
+
+    pub fn add_directive(self: &mut Self, directive: raw_directive::RawDirective) {
+        self.directives.push(directive);
+    }
+
+    // Removes the ledger's transactions and directives, returning them ordered by date; on the
+    //  same date, directives precede transactions and each keeps its order
+    pub fn take_entries(self: &mut Self) -> Vec<LedgerEntry> {
+        let mut entries: Vec<LedgerEntry> = std::mem::take(&mut self.directives).into_iter().map(LedgerEntry::Directive)
+            .chain(std::mem::take(&mut self.xns).into_iter().map(LedgerEntry::Transaction))
+            .collect();
+        entries.sort_by(|a, b| a.date().cmp(b.date()));
+
+        return entries;
+    }
+
+    // Adds the entries, such as those returned by a plugin, restoring the order in which they
+    //  appear in the sources. An entry made by a plugin takes the position of the item whose
+    //  ParserInfo it copies; entries without a source follow the rest in the order given.
+    pub fn add_entries(self: &mut Self, entries: Vec<LedgerEntry>) {
+        for entry in entries {
+            match entry {
+                LedgerEntry::Transaction(xn) => { self.add_transaction(xn); }
+                LedgerEntry::Directive(d) => { self.add_directive(d); }
+            }
+        }
+
+        let sources = &self.sources;
+        self.xns.sort_by_cached_key(|xn| source_order(sources, &xn.pinfo));
+        self.directives.sort_by_cached_key(|d| source_order(sources, d.pinfo()));
+    }
+
+    pub fn add_plugin(self: &mut Self, plugin: raw_directive::RawPlugin) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn plugins(self: &Self) -> std::slice::Iter<'_, raw_directive::RawPlugin> {
+        return self.plugins.iter();
+    }
+
+    pub fn options(self: &Self) -> &LedgerOptions {
+        return &self.options;
+    }
+
+    pub fn options_mut(self: &mut Self) -> &mut LedgerOptions {
+        return &mut self.options;
+    }
+
+    // Registers a string (None) or file from which items are parsed; 'include' locates the include
+    //  which named the file, or is the default for the root
+    pub fn add_source(self: &mut Self, file: Option<Arc<Path>>, include: &ParserInfo) -> SourceId {
+        let included_at = (include.source != SourceId::default()).then_some((include.source, include.position.offset));
+        self.sources.push(Source { file, included_at });
+        return SourceId(self.sources.len());
+    }
+
+    // The file of a source; None for string sources and unknown ids
+    pub fn source_file(self: &Self, id: SourceId) -> Option<&Path> {
+        return self.sources.get(id.0.checked_sub(1)?)?.file.as_deref();
+    }
+
+    pub fn source_count(self: &Self) -> usize {
+        return self.sources.len();
+    }
+
+
+    pub fn transactions(self: &Self) -> std::slice::Iter<'_, raw_transaction::RawTransaction> {
+        return self.xns.iter();
+    }
+
+    pub fn transactions_tagged<'a>(self: &'a Self, tag: &'a str) -> impl Iterator<Item = &'a raw_transaction::RawTransaction> {
+        return self.xns.iter().filter(move |xn| xn.tags.contains(tag));
+    }
+
+    pub fn transactions_linked<'a>(self: &'a Self, link: &'a str) -> impl Iterator<Item = &'a raw_transaction::RawTransaction> {
+        return self.xns.iter().filter(move |xn| xn.links.contains(link));
+    }
+
+    pub fn directives(self: &Self) -> std::slice::Iter<'_, raw_directive::RawDirective> {
+        return self.directives.iter();
+    }
+
+    // Allows passes run after parsing, such as booking, to complete the transactions in place
+    pub fn transactions_mut(self: &mut Self) -> std::slice::IterMut<'_, raw_transaction::RawTransaction> {
+        return self.xns.iter_mut();
+    }
+
+    // The notes attached to the account, in ledger order
+    pub fn notes_of<'a>(self: &'a Self, acct: &'a str) -> impl Iterator<Item = &'a RawNote> {
+        return self.directives.iter()
+            .filter_map(|d| match d { RawDirective::Note(n) => Some(n), _ => None })
+            .filter(move |n| n.acct.path == acct);
+    }
+
+    // The documents attached to the account, in ledger order
+    pub fn documents_of<'a>(self: &'a Self, acct: &'a str) -> impl Iterator<Item = &'a RawDocument> {
+        return self.directives.iter()
+            .filter_map(|d| match d { RawDirective::Document(doc) => Some(doc), _ => None })
+            .filter(move |doc| doc.acct.path == acct);
+    }
+
+    pub fn events(self: &Self) -> impl Iterator<Item = &RawEvent> {
+        return self.directives.iter()
+            .filter_map(|d| match d { RawDirective::Event(e) => Some(e), _ => None });
+    }
+
+    // The distinct account paths referenced by directives and postings, in sorted order
+    pub fn accounts(self: &Self) -> impl Iterator<Item = &str> {
+        let mut accts = BTreeSet::new();
+
+        for d in &self.directives {
+            match d {
+                RawDirective::Open(o) => { accts.insert(o.acct.path.as_str()); }
+                RawDirective::Close(c) => { accts.insert(c.acct.path.as_str()); }
+                RawDirective::Balance(b) => { accts.insert(b.acct.path.as_str()); }
+                RawDirective::Note(n) => { accts.insert(n.acct.path.as_str()); }
+                RawDirective::Document(d) => { accts.insert(d.acct.path.as_str()); }
+                RawDirective::Pad(p) => {
+                    accts.insert(p.acct.path.as_str());
+                    accts.insert(p.source_acct.path.as_str());
+                }
+                RawDirective::Commodity(_) | RawDirective::Event(_) | RawDirective::Price(_) => {}
+            }
+        }
+
+        for xn in &self.xns {
+            for p in &xn.postings {
+                accts.insert(p.acct.path.as_str());
+            }
+        }
+
+        return accts.into_iter();
+    }
+
+    // The distinct commodities referenced by directives and postings, in sorted order
+    pub fn commodities(self: &Self) -> impl Iterator<Item = &str> {
+        let mut cmdties = BTreeSet::new();
+
+        for d in &self.directives {
+            match d {
+                RawDirective::Commodity(c) => { cmdties.insert(c.currency.as_str()); }
+                RawDirective::Balance(b) => { cmdties.insert(b.amount.commodity.as_str()); }
+                RawDirective::Price(p) => {
+                    cmdties.insert(p.currency.as_str());
+                    cmdties.insert(p.amount.commodity.as_str());
+                }
+                RawDirective::Open(_) | RawDirective::Close(_) | RawDirective::Note(_)
+                    | RawDirective::Document(_) | RawDirective::Event(_) | RawDirective::Pad(_) => {}
+            }
+        }
+
+        for xn in &self.xns {
+            cmdties.extend(xn.postings.iter().filter_map(|p| p.commodity()));
+        }
+
+        return cmdties.into_iter();
+    }
+
+
+    // The number of transactions and directives in the ledger
+    pub fn len(self: &Self) -> usize {
+        return self.xns.len() + self.directives.len();
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        return self.xns.is_empty() && self.directives.is_empty();
+    }
+
+
+    // Transactions dated on or after 'begin' and before 'end' (YYYY-MM-DD)
+    pub fn transactions_between<'a>(self: &'a Self, begin: &'a str, end: &'a str) -> impl Iterator<Item = &'a raw_transaction::RawTransaction> {
+        return self.xns.iter()
+            .filter(move |xn| xn.date.as_str() >= begin && xn.date.as_str() < end);
+    }
+
+    // Directives dated on or after 'begin' and before 'end' (YYYY-MM-DD)
+    pub fn directives_between<'a>(self: &'a Self, begin: &'a str, end: &'a str) -> impl Iterator<Item = &'a raw_directive::RawDirective> {
+        return self.directives.iter()
+            .filter(move |d| d.date() >= begin && d.date() < end);
+    }
+
+
+    // Finds the transaction or directive at the given source line, in any source. A transaction
+    //  is found by the line of its header or of any of its postings.
+    pub fn find_by_line(self: &Self, line: usize) -> Option<LedgerItem<'_>> {
+        for xn in &self.xns {
+            let last = xn.postings.last().map_or(xn.pinfo.position.line, |p| p.pinfo.position.line);
+            if xn.pinfo.position.line <= line && line <= last {
+                return Some(LedgerItem::Transaction(xn));
+            }
+        }
+
+        return self.directives.iter()
+            .find(|d| d.pinfo().position.line == line)
+            .map(LedgerItem::Directive);
+    }
+
+    // Finds the transaction or directive whose text spans the byte offset of the source
+    pub fn find_at(self: &Self, source: SourceId, offset: usize) -> Option<LedgerItem<'_>> {
+        let spans = |pinfo: &ParserInfo| pinfo.source == source
+            && pinfo.position.offset <= offset && offset < pinfo.end.offset;
+
+        return self.xns.iter().find(|xn| spans(&xn.pinfo)).map(LedgerItem::Transaction)
+            .or_else(|| self.directives.iter().find(|d| spans(d.pinfo())).map(LedgerItem::Directive));
+    }
+}
+
+// Orders items as they are parsed: by offset within a source, with the items of an included
+//  file at the offset of its include. Items without a source sort last.
+fn source_order(sources: &[Source], pinfo: &ParserInfo) -> (bool, Vec<(usize, SourceId)>) {
+    let mut order = vec![(pinfo.position.offset, pinfo.source)];
+    let mut current = pinfo.source;
+    while let Some(source) = current.0.checked_sub(1).and_then(|i| sources.get(i)) {
+        let Some((parent, offset)) = source.included_at else {
+            break;
+        };
+        order.push((offset, current));
+        current = parent;
+    }
+    order.reverse();
+
+    return (pinfo.source == SourceId::default(), order);
+}
+
+
+#[cfg(test)]
+mod parsed_ledger_tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn pinfo(line: usize) -> ParserInfo {
+        return ParserInfo { position: FilePosition { line, col: 1, offset: 0 }, ..Default::default() };
+    }
+
+    fn acct(path: &str) -> RawAccountDescriptor {
+        return RawAccountDescriptor { path: path.to_string(), ..Default::default() };
+    }
+
+    fn posting(path: &str, line: usize) -> RawPosting {
+        return RawPosting { acct: acct(path), pinfo: pinfo(line), ..Default::default() };
+    }
+
+    fn sample_ledger() -> ParsedLedger {
+        let mut pl = ParsedLedger::default();
+
+        pl.add_directive(RawDirective::Open(RawOpen { date: "2001-09-11".to_string(), acct: acct("Assets:Cash"), pinfo: pinfo(1), ..Default::default() }));
+        pl.add_directive(RawDirective::Commodity(RawCommodity { date: "2001-09-11".to_string(), currency: "USD".to_string(), pinfo: pinfo(2), ..Default::default() }));
+        pl.add_directive(RawDirective::Balance(RawBalance { date: "2001-10-01".to_string(), acct: acct("Assets:Cash"), amount: Amount::new(Decimal::zero(), "BTC"), pinfo: pinfo(3), ..Default::default() }));
+
+        pl.add_transaction(RawTransaction {
+            date: "2001-09-12".to_string(),
+            postings: vec![posting("Assets:Cash", 6), posting("Equity", 7)],
+            pinfo: pinfo(5),
+            ..Default::default()
+        });
+        pl.add_transaction(RawTransaction {
+            date: "2001-10-01".to_string(),
+            postings: vec![posting("Assets:Cash", 10), posting("Income", 11)],
+            pinfo: pinfo(9),
+            ..Default::default()
+        });
+
+        return pl;
+    }
+
+    #[test]
+    fn can_count_items() {
+        assert!(ParsedLedger::default().is_empty());
+
+        let pl = sample_ledger();
+        assert!(!pl.is_empty());
+        assert_eq!(pl.len(), 5);
+        assert_eq!(pl.transactions().count(), 2);
+        assert_eq!(pl.directives().count(), 3);
+    }
+
+    #[test]
+    fn can_take_and_add_entries() {
+        let mut pl = sample_ledger();
+
+        let entries = pl.take_entries();
+        assert!(pl.is_empty());
+        assert_eq!(entries.iter().map(|e| e.pinfo().position.line).collect::<Vec<_>>(), vec![1, 2, 5, 3, 9]);
+        assert_eq!(entries.iter().map(|e| e.date()).collect::<Vec<_>>(),
+            vec!["2001-09-11", "2001-09-11", "2001-09-12", "2001-10-01", "2001-10-01"]);
+
+        pl.add_entries(entries);
+        assert_eq!(pl.transactions().count(), 2);
+        assert_eq!(pl.directives().count(), 3);
+    }
+
+    #[test]
+    fn can_list_accounts_and_commodities() {
+        let pl = sample_ledger();
+        assert_eq!(pl.accounts().collect::<Vec<_>>(), vec!["Assets:Cash", "Equity", "Income"]);
+        assert_eq!(pl.commodities().collect::<Vec<_>>(), vec!["BTC", "USD"]);
+    }
+
+    #[rstest]
+    #[case ("2001-01-01", "2002-01-01", 2, 3)]
+    #[case ("2001-09-12", "2001-10-01", 1, 0)]
+    #[case ("2001-10-01", "2001-10-02", 1, 1)]
+    #[case ("2002-01-01", "2003-01-01", 0, 0)]
+    fn can_filter_by_date(#[case] begin: &str, #[case] end: &str, #[case] xns: usize, #[case] directives: usize) {
+        let pl = sample_ledger();
+        assert_eq!(pl.transactions_between(begin, end).count(), xns);
+        assert_eq!(pl.directives_between(begin, end).count(), directives);
+    }
+
+    #[rstest]
+    #[case (2, Some("2001-09-11"))]
+    #[case (5, Some("2001-09-12"))]
+    #[case (7, Some("2001-09-12"))]
+    #[case (11, Some("2001-10-01"))]
+    #[case (8, None)]
+    #[case (42, None)]
+    fn can_find_by_line(#[case] line: usize, #[case] date: Option<&str>) {
+        let pl = sample_ledger();
+        let found = pl.find_by_line(line).map(|item| match item {
+            LedgerItem::Transaction(xn) => xn.date.as_str(),
+            LedgerItem::Directive(d) => d.date(),
+        });
+        assert_eq!(found, date);
+    }
+
+    #[test]
+    fn can_restore_source_order() {
+        let mut pl = ParsedLedger::default();
+        let root = pl.add_source(None, &ParserInfo::default());
+        let include = ParserInfo { position: FilePosition { line: 3, col: 1, offset: 50 }, source: root, ..Default::default() };
+        let included = pl.add_source(Some(Arc::from(Path::new("included.ledger"))), &include);
+
+        let at = |source: SourceId, offset: usize| ParserInfo { position: FilePosition { line: 1, col: 1, offset }, source, ..Default::default() };
+        for (date, pinfo) in [("2001-09-13", at(root, 10)), ("2001-09-11", at(included, 0)), ("2001-09-12", at(root, 80)), ("2001-09-12", at(SourceId::default(), 0))] {
+            pl.add_transaction(RawTransaction { date: date.to_string(), pinfo, ..Default::default() });
+        }
+
+        let entries = pl.take_entries();
+        assert_eq!(entries.iter().map(|e| e.date()).collect::<Vec<_>>(), vec!["2001-09-11", "2001-09-12", "2001-09-12", "2001-09-13"]);
+
+        pl.add_entries(entries);
+        assert_eq!(pl.transactions().map(|xn| xn.date.as_str()).collect::<Vec<_>>(), vec!["2001-09-13", "2001-09-11", "2001-09-12", "2001-09-12"]);
+        assert_eq!(pl.transactions().last().unwrap().pinfo.source, SourceId::default());
+    }
+
+    #[rstest]
+    #[case (1, 0, Some("2001-09-11"))]
+    #[case (1, 9, Some("2001-09-11"))]
+    #[case (1, 10, None)]
+    #[case (2, 5, Some("2001-09-12"))]
+    #[case (3, 5, None)]
+    fn can_find_at_offset(#[case] source: usize, #[case] offset: usize, #[case] date: Option<&str>) {
+        let mut pl = ParsedLedger::default();
+        let first = pl.add_source(None, &ParserInfo::default());
+        let second = pl.add_source(Some(Arc::from(Path::new("second.ledger"))), &ParserInfo::default());
+        assert_eq!(pl.source_count(), 2);
+        assert_eq!(pl.source_file(first), None);
+        assert_eq!(pl.source_file(second), Some(Path::new("second.ledger")));
+        assert_eq!(pl.source_file(SourceId::default()), None);
+
+        let span = |source: SourceId, start: usize, end: usize| ParserInfo {
+            position: FilePosition { line: 1, col: start + 1, offset: start },
+            end: FilePosition { line: 1, col: end + 1, offset: end },
+            source,
+            ..Default::default()
+        };
+
+        pl.add_directive(RawDirective::Commodity(RawCommodity { date: "2001-09-11".to_string(), pinfo: span(first, 0, 10), ..Default::default() }));
+        pl.add_transaction(RawTransaction { date: "2001-09-12".to_string(), pinfo: span(second, 0, 10), ..Default::default() });
+
+        let found = pl.find_at(SourceId(source), offset).map(|item| match item {
+            LedgerItem::Transaction(xn) => xn.date.as_str(),
+            LedgerItem::Directive(d) => d.date(),
+        });
+        assert_eq!(found, date);
+    }
+}
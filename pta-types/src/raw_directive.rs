@@ -0,0 +1,245 @@
+// Copyright (C) 2023, AltaModa Technologies, LLC. All rights reserved.
+//
+// This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
+//
+
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use super::*;
+
+
+// The method of matching lots when reducing an account's positions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BookingMethod {
+    #[default]
+    Strict,
+    StrictWithSize,
+    Fifo,
+    Lifo,
+    Hifo,
+    Average,
+    None,
+}
+
+impl FromStr for BookingMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "STRICT" => { return Ok(BookingMethod::Strict); }
+            "STRICT_WITH_SIZE" => { return Ok(BookingMethod::StrictWithSize); }
+            "FIFO" => { return Ok(BookingMethod::Fifo); }
+            "LIFO" => { return Ok(BookingMethod::Lifo); }
+            "HIFO" => { return Ok(BookingMethod::Hifo); }
+            "AVERAGE" => { return Ok(BookingMethod::Average); }
+            "NONE" => { return Ok(BookingMethod::None); }
+            _ => { return Err(format!("unknown booking method '{s}'")); }
+        }
+    }
+}
+
+impl fmt::Display for BookingMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BookingMethod::Strict => "STRICT",
+            BookingMethod::StrictWithSize => "STRICT_WITH_SIZE",
+            BookingMethod::Fifo => "FIFO",
+            BookingMethod::Lifo => "LIFO",
+            BookingMethod::Hifo => "HIFO",
+            BookingMethod::Average => "AVERAGE",
+            BookingMethod::None => "NONE",
+        };
+        write!(f, "{s}")
+    }
+}
+
+
+// YYYY-MM-DD open Account [ConstraintCurrency,...] ["BookingMethod"]
+#[derive(Debug, Default, Clone)]
+pub struct RawOpen {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    // When not empty, postings to the account must be in one of these currencies
+    pub currencies: Vec<String>,
+    pub booking: Option<BookingMethod>,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD close Account
+#[derive(Debug, Default, Clone)]
+pub struct RawClose {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD commodity Currency
+#[derive(Debug, Default, Clone)]
+pub struct RawCommodity {
+    pub date: String,
+    pub currency: String,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD balance Account Amount
+#[derive(Debug, Default, Clone)]
+pub struct RawBalance {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    pub amount: Amount,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD note Account "Description"
+#[derive(Debug, Default, Clone)]
+pub struct RawNote {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    pub text: String,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD document Account "PathToDocument"
+#[derive(Debug, Default, Clone)]
+pub struct RawDocument {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    // The path as written in the ledger
+    pub path: String,
+    // The path resolved relative to the directory of the ledger file (or the current directory)
+    pub resolved: PathBuf,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD event "Name" "Value"
+#[derive(Debug, Default, Clone)]
+pub struct RawEvent {
+    pub date: String,
+    pub name: String,
+    pub value: String,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD pad Account AccountPad
+#[derive(Debug, Default, Clone)]
+pub struct RawPad {
+    pub date: String,
+    pub acct: RawAccountDescriptor,
+    // The account from which the padding amount is taken
+    pub source_acct: RawAccountDescriptor,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// YYYY-MM-DD price Commodity Price
+#[derive(Debug, Default, Clone)]
+pub struct RawPrice {
+    pub date: String,
+    pub currency: String,
+    // The price of one unit of the currency
+    pub amount: Amount,
+    pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+// plugin "ModuleName" ["StringConfig"]; plugins are not dated entries, so the ledger keeps
+//  them apart, in the order they appear
+#[derive(Debug, Default, Clone)]
+pub struct RawPlugin {
+    pub name: String,
+    pub config: Option<String>,
+    pub comment: String,
+    pub pinfo: ParserInfo,
+}
+
+
+// The directives of a ledger, kept in the order they appear
+#[derive(Debug, Clone)]
+pub enum RawDirective {
+    Open(RawOpen),
+    Close(RawClose),
+    Commodity(RawCommodity),
+    Balance(RawBalance),
+    Note(RawNote),
+    Document(RawDocument),
+    Event(RawEvent),
+    Pad(RawPad),
+    Price(RawPrice),
+}
+
+impl RawDirective {
+    pub fn date(&self) -> &str {
+        match self {
+            RawDirective::Open(d) => &d.date,
+            RawDirective::Close(d) => &d.date,
+            RawDirective::Commodity(d) => &d.date,
+            RawDirective::Balance(d) => &d.date,
+            RawDirective::Note(d) => &d.date,
+            RawDirective::Document(d) => &d.date,
+            RawDirective::Event(d) => &d.date,
+            RawDirective::Pad(d) => &d.date,
+            RawDirective::Price(d) => &d.date,
+        }
+    }
+
+    pub fn pinfo(&self) -> &ParserInfo {
+        match self {
+            RawDirective::Open(d) => &d.pinfo,
+            RawDirective::Close(d) => &d.pinfo,
+            RawDirective::Commodity(d) => &d.pinfo,
+            RawDirective::Balance(d) => &d.pinfo,
+            RawDirective::Note(d) => &d.pinfo,
+            RawDirective::Document(d) => &d.pinfo,
+            RawDirective::Event(d) => &d.pinfo,
+            RawDirective::Pad(d) => &d.pinfo,
+            RawDirective::Price(d) => &d.pinfo,
+        }
+    }
+
+    pub fn meta(&self) -> &Metadata {
+        match self {
+            RawDirective::Open(d) => &d.meta,
+            RawDirective::Close(d) => &d.meta,
+            RawDirective::Commodity(d) => &d.meta,
+            RawDirective::Balance(d) => &d.meta,
+            RawDirective::Note(d) => &d.meta,
+            RawDirective::Document(d) => &d.meta,
+            RawDirective::Event(d) => &d.meta,
+            RawDirective::Pad(d) => &d.meta,
+            RawDirective::Price(d) => &d.meta,
+        }
+    }
+
+    // Applies 'f' to the parser info of the directive and everything within it
+    pub fn for_each_pinfo_mut(&mut self, f: &mut dyn FnMut(&mut ParserInfo)) {
+        match self {
+            RawDirective::Open(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); }
+            RawDirective::Close(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); }
+            RawDirective::Commodity(d) => { f(&mut d.pinfo); }
+            RawDirective::Balance(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); }
+            RawDirective::Note(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); }
+            RawDirective::Document(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); }
+            RawDirective::Event(d) => { f(&mut d.pinfo); }
+            RawDirective::Pad(d) => { f(&mut d.pinfo); f(&mut d.acct.pinfo); f(&mut d.source_acct.pinfo); }
+            RawDirective::Price(d) => { f(&mut d.pinfo); }
+        }
+    }
+}
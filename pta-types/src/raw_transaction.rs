@@ -3,30 +3,174 @@
 // This project is licensed under the terms of the MIT license (cf. LICENSE file in root).
 //
 
+use std::collections::BTreeSet;
+
 use super::*;
 
 
-#[derive(Default, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct RawAccountDescriptor {
     pub path: String,
     pub pinfo: ParserInfo,
 }
 
 
-#[derive(Default, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct RawTransaction {
     pub date: String,
     pub anno: String,
     pub desc: String,
+    // The names of the transaction's tags (#tag) and links (^link), without their prefix
+    pub tags: BTreeSet<String>,
+    pub links: BTreeSet<String>,
     pub postings: Vec<RawPosting>,
     pub comment: String,
+    pub meta: Metadata,
+    pub pinfo: ParserInfo,
+}
+
+impl RawTransaction {
+    // Applies 'f' to the parser info of the transaction and everything within it
+    pub fn for_each_pinfo_mut(&mut self, f: &mut dyn FnMut(&mut ParserInfo)) {
+        f(&mut self.pinfo);
+
+        for p in &mut self.postings {
+            f(&mut p.pinfo);
+            f(&mut p.acct.pinfo);
+            if let Some(cost) = &mut p.cost {
+                f(&mut cost.pinfo);
+            }
+            if let Some(price) = &mut p.price {
+                f(&mut price.pinfo);
+            }
+            if let Some(assertion) = &mut p.assertion {
+                f(&mut assertion.pinfo);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum CostKind {
+    #[default]
+    PerUnit,    // {...}
+    Total,      // {{...}}
+}
+
+// The cost basis of a posting's units, e.g., { 381.9697397 USD, 2016-01-28, "lot1" }. Each
+//  component is optional; an empty spec matches any lot when reducing a position.
+#[derive(Debug, Default, Clone)]
+pub struct Cost {
+    pub kind: CostKind,
+    pub amount: Option<Amount>,
+    pub date: Option<String>,
+    pub label: Option<String>,
+    pub pinfo: ParserInfo,
+}
+
+impl Cost {
+    // The total cost of the given units; signed like the units. None if the spec has no amount.
+    pub fn total_for(&self, units: &Decimal) -> Option<Amount> {
+        let amount = self.amount.as_ref()?;
+
+        match self.kind {
+            CostKind::PerUnit => { return amount.checked_mul(units); }
+            CostKind::Total => {
+                let total = amount.number.checked_abs()?;
+                let number = if units.is_negative() { total.checked_neg()? } else { total };
+                return Some(Amount::new(number, &amount.commodity));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum PriceKind {
+    #[default]
+    PerUnit,    // @
+    Total,      // @@
+}
+
+// The price at which a posting's units convert to another currency, e.g., @ 1.08 USD
+#[derive(Debug, Default, Clone)]
+pub struct Price {
+    pub kind: PriceKind,
+    pub amount: Amount,
+    pub pinfo: ParserInfo,
+}
+
+impl Price {
+    // The value of the given units in the price's currency; signed like the units
+    pub fn converted(&self, units: &Decimal) -> Option<Amount> {
+        match self.kind {
+            PriceKind::PerUnit => { return self.amount.checked_mul(units); }
+            PriceKind::Total => {
+                let total = self.amount.number.checked_abs()?;
+                let number = if units.is_negative() { total.checked_neg()? } else { total };
+                return Some(Amount::new(number, &self.amount.commodity));
+            }
+        }
+    }
+}
+
+// A balance assertion on a posting, e.g., = 10 USD: the balance its account must have just after
+//  the posting
+#[derive(Debug, Default, Clone)]
+pub struct PostingAssertion {
+    pub amount: Amount,
+    // == ; the account must hold no other commodity
+    pub sole: bool,
+    // =* ; the balance includes the account's sub-accounts
+    pub inclusive: bool,
     pub pinfo: ParserInfo,
 }
 
-#[derive(Default, Clone)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PostingKind {
+    #[default]
+    Real,
+    Virtual,            // (Account); not balanced
+    BalancedVirtual,    // [Account]; balanced among themselves
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct RawPosting {
     pub acct: RawAccountDescriptor,
-    pub value: f64,
+    pub kind: PostingKind,
+    // None when elided in the ledger; see 'inferred'
+    pub amount: Option<Amount>,
+    // Set when the amount was elided and inferred from the transaction's other postings
+    pub inferred: bool,
+    pub cost: Option<Cost>,
+    pub price: Option<Price>,
+    pub assertion: Option<PostingAssertion>,
     pub comment: String,
+    pub meta: Metadata,
     pub pinfo: ParserInfo,
 }
+
+impl RawPosting {
+    // The amount the posting contributes to the transaction's balance: its cost if it has one,
+    //  else its price conversion, else its own amount. None when the amount is elided or a
+    //  cost spec has no amount.
+    pub fn weight(&self) -> Option<Amount> {
+        let amount = self.amount.as_ref()?;
+
+        if let Some(cost) = &self.cost {
+            return cost.total_for(&amount.number);
+        }
+
+        if let Some(price) = &self.price {
+            return price.converted(&amount.number);
+        }
+
+        return Some(amount.clone());
+    }
+
+    // The commodity of the posting's amount, if the ledger specified one
+    pub fn commodity(&self) -> Option<&str> {
+        return self.amount.as_ref()
+            .map(|a| a.commodity.as_str())
+            .filter(|c| !c.is_empty());
+    }
+}